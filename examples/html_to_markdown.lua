@@ -0,0 +1 @@
+qlp.result = qlp.markdown or qlp.text
@@ -1,8 +1,12 @@
 local url = qlp.text
 
-local owner, repos, pull = string.match(url, "^https://github.com/([-0-9a-z./_]+)/([-0-9a-z./-]+)/pull/(%d+)$")
+-- also matches links to the files or commits of a pull request, with a query or a fragment
+local pr = re.captures([[^\s*https://github\.com/(?<owner>[\w.-]+)/(?<repo>[\w.-]+)/pull/(?<number>\d+)(?:[/?#]\S*)?\s*$]], url)
+if pr == nil then
+    error("not a pull request URL: " .. url)
+end
 
-local a = "https://api.github.com/repos/" .. owner .. "/" .. repos .. "/pulls/" .. pull
+local a = "https://api.github.com/repos/" .. pr.owner .. "/" .. pr.repo .. "/pulls/" .. pr.number
 
 local token = "<your token...>"
 
@@ -0,0 +1,277 @@
+//! Block layout of HTML shared by the text renderers
+//!
+//! Paragraphs, quotes and lists are laid out the same in Markdown and plain text,
+//! an [`Emitter`] renders text, inline elements and the other blocks in its own syntax.
+
+use markup5ever_rcdom::{Handle, NodeData};
+
+use crate::html::{Format, apply_format, get_attr, is_element};
+
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "blockquote",
+    "body",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "header",
+    "hr",
+    "html",
+    "li",
+    "main",
+    "nav",
+    "ol",
+    "p",
+    "pre",
+    "section",
+    "summary",
+    "table",
+    "ul",
+];
+
+const SKIPPED_TAGS: &[&str] = &[
+    "head", "link", "meta", "noscript", "script", "style", "template", "title",
+];
+
+/// The syntax of one output.
+pub trait Emitter {
+    /// Renders the text of a text node, whitespace is already collapsed.
+    fn text(&self, text: &str) -> String;
+
+    /// Joins the cleaned lines of a paragraph.
+    fn paragraph(&self, lines: &[String]) -> String;
+
+    /// Renders an inline element.
+    fn inline(&self, handle: &Handle, parent: Format) -> String;
+
+    /// Renders a block other than quotes and lists, `None` lays out its children as blocks.
+    fn block(&self, handle: &Handle, format: Format) -> Option<String>;
+
+    /// Separates the blocks of a list item.
+    fn item_separator(&self, _li: &Handle) -> &'static str {
+        "\n"
+    }
+}
+
+pub fn tag_of(handle: &Handle) -> Option<&str> {
+    match handle.data {
+        NodeData::Element { ref name, .. } => Some(name.local.as_ref()),
+        _ => None,
+    }
+}
+
+pub fn is_block(handle: &Handle) -> bool {
+    tag_of(handle).is_some_and(|t| BLOCK_TAGS.contains(&t))
+}
+
+fn is_skipped(handle: &Handle) -> bool {
+    tag_of(handle).is_some_and(|t| SKIPPED_TAGS.contains(&t))
+}
+
+/// Returns whether `handle` has a block element below it.
+fn has_block(handle: &Handle) -> bool {
+    handle
+        .children
+        .borrow()
+        .iter()
+        .any(|c| !is_skipped(c) && (is_block(c) || has_block(c)))
+}
+
+/// Renders the children of `handle` as blocks, joining loose inline content into paragraphs.
+pub fn render_blocks<E: Emitter>(
+    emitter: &E,
+    handle: &Handle,
+    format: Format,
+    out: &mut Vec<String>,
+) {
+    let mut paragraph = String::new();
+
+    let children = handle.children.borrow();
+    for child in children.iter() {
+        match child.data {
+            NodeData::Text { ref contents } => {
+                paragraph.push_str(&emitter.text(&collapse_whitespace(contents.borrow().as_ref())))
+            }
+            NodeData::Element { .. } => {
+                if is_skipped(child) {
+                    continue;
+                }
+
+                if is_block(child) || has_block(child) {
+                    flush_paragraph(emitter, &mut paragraph, out);
+                    render_block(emitter, child, format, out);
+                } else {
+                    paragraph.push_str(&emitter.inline(child, format));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    flush_paragraph(emitter, &mut paragraph, out);
+}
+
+fn flush_paragraph<E: Emitter>(emitter: &E, paragraph: &mut String, out: &mut Vec<String>) {
+    let lines = clean_lines(paragraph);
+    if !lines.is_empty() {
+        out.push(emitter.paragraph(&lines));
+    }
+
+    paragraph.clear();
+}
+
+fn render_block<E: Emitter>(emitter: &E, handle: &Handle, format: Format, out: &mut Vec<String>) {
+    let format = apply_format(handle, format);
+
+    let text = match tag_of(handle).unwrap_or_default() {
+        "blockquote" => {
+            let mut inner = Vec::new();
+            render_blocks(emitter, handle, format, &mut inner);
+            prefix_lines(&inner.join("\n\n"), "> ", ">")
+        }
+        "ul" | "ol" => render_list(emitter, handle, format),
+        _ => match emitter.block(handle, format) {
+            Some(text) => text,
+            None => return render_blocks(emitter, handle, format, out),
+        },
+    };
+
+    if !text.is_empty() {
+        out.push(text);
+    }
+}
+
+/// Renders the children of an inline element.
+pub fn render_inline_children<E: Emitter>(emitter: &E, handle: &Handle, format: Format) -> String {
+    let mut text = String::new();
+
+    let children = handle.children.borrow();
+    for child in children.iter() {
+        match child.data {
+            NodeData::Text { ref contents } => {
+                text.push_str(&emitter.text(&collapse_whitespace(contents.borrow().as_ref())))
+            }
+            NodeData::Element { .. } if !is_skipped(child) => {
+                text.push_str(&emitter.inline(child, format))
+            }
+            _ => {}
+        }
+    }
+
+    text
+}
+
+fn render_list<E: Emitter>(emitter: &E, list: &Handle, format: Format) -> String {
+    let ordered = is_element(list, "ol");
+    let mut number = get_attr(list, "start")
+        .and_then(|s| s.trim().parse::<i64>().ok())
+        .unwrap_or(1);
+
+    let mut items: Vec<String> = Vec::new();
+    let mut indent = 2;
+
+    let children = list.children.borrow();
+    for child in children.iter() {
+        if is_element(child, "li") {
+            let marker = if ordered {
+                format!("{}. ", number)
+            } else {
+                "- ".to_string()
+            };
+            number += 1;
+            indent = marker.len();
+
+            let mut blocks = Vec::new();
+            render_blocks(emitter, child, apply_format(child, format), &mut blocks);
+
+            let body = blocks.join(emitter.item_separator(child));
+            items.push(format!("{}{}", marker, indent_lines(&body, indent)));
+        } else if is_element(child, "ul") || is_element(child, "ol") {
+            // a list put directly in a list belongs to the previous item
+            let nested = render_list(emitter, child, format);
+            match items.last_mut() {
+                Some(last) => {
+                    last.push('\n');
+                    last.push_str(&" ".repeat(indent));
+                    last.push_str(&indent_lines(&nested, indent));
+                }
+                None => items.push(nested),
+            }
+        }
+    }
+
+    items.join("\n")
+}
+
+/// Splits rendered inline text into lines, collapsing spaces and dropping empty lines.
+pub fn clean_lines(text: &str) -> Vec<String> {
+    text.split('\n')
+        .map(|l| {
+            l.split(' ')
+                .filter(|w| !w.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+        .filter(|l| !l.is_empty())
+        .collect()
+}
+
+pub fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut in_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !in_space {
+                collapsed.push(' ');
+            }
+            in_space = true;
+        } else {
+            collapsed.push(c);
+            in_space = false;
+        }
+    }
+
+    collapsed
+}
+
+fn indent_lines(text: &str, indent: usize) -> String {
+    let padding = " ".repeat(indent);
+    text.lines()
+        .enumerate()
+        .map(|(i, l)| {
+            if i == 0 || l.is_empty() {
+                l.to_string()
+            } else {
+                format!("{}{}", padding, l)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn prefix_lines(text: &str, prefix: &str, empty_prefix: &str) -> String {
+    text.lines()
+        .map(|l| {
+            if l.is_empty() {
+                empty_prefix.to_string()
+            } else {
+                format!("{}{}", prefix, l)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
@@ -3,13 +3,89 @@ use mlua::Lua;
 
 pub fn init(lua: &Lua) -> mlua::Result<()> {
     {
+        use crate::builtins::json::JsonArray;
+        use crate::builtins::json::JsonNull;
+        use crate::builtins::json::JsonObject;
         use crate::builtins::json::JsonToTable;
         use crate::builtins::json::MinifyJson;
         use crate::builtins::json::PrettierJson;
+        use crate::builtins::json::TableToJson;
 
         let _ = PrettierJson {}.set_function(lua);
         let _ = MinifyJson {}.set_function(lua);
         let _ = JsonToTable {}.set_function(lua);
+        let _ = TableToJson {}.set_function(lua);
+        let _ = JsonArray {}.set_function(lua);
+        let _ = JsonObject {}.set_function(lua);
+        let _ = JsonNull {}.set_value(lua);
+    }
+
+    {
+        use crate::builtins::yaml::JsonToYaml;
+        use crate::builtins::yaml::TableToYaml;
+        use crate::builtins::yaml::YamlDocuments;
+        use crate::builtins::yaml::YamlToJson;
+        use crate::builtins::yaml::YamlToTable;
+
+        let _ = YamlToTable {}.set_function(lua);
+        let _ = YamlDocuments {}.set_function(lua);
+        let _ = TableToYaml {}.set_function(lua);
+        let _ = JsonToYaml {}.set_function(lua);
+        let _ = YamlToJson {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::toml::JsonToToml;
+        use crate::builtins::toml::TableToToml;
+        use crate::builtins::toml::TomlToJson;
+        use crate::builtins::toml::TomlToTable;
+        use crate::builtins::toml::TomlToYaml;
+        use crate::builtins::toml::YamlToToml;
+
+        let _ = TomlToTable {}.set_function(lua);
+        let _ = TableToToml {}.set_function(lua);
+        let _ = JsonToToml {}.set_function(lua);
+        let _ = TomlToJson {}.set_function(lua);
+        let _ = YamlToToml {}.set_function(lua);
+        let _ = TomlToYaml {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::csv::CsvToTable;
+        use crate::builtins::csv::TableToCsv;
+
+        let _ = CsvToTable {}.set_function(lua);
+        let _ = TableToCsv {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::xml::MinifyXml;
+        use crate::builtins::xml::PrettierXml;
+        use crate::builtins::xml::TableToXml;
+        use crate::builtins::xml::XmlQuery;
+        use crate::builtins::xml::XmlToTable;
+
+        let _ = XmlToTable {}.set_function(lua);
+        let _ = TableToXml {}.set_function(lua);
+        let _ = PrettierXml {}.set_function(lua);
+        let _ = MinifyXml {}.set_function(lua);
+        let _ = XmlQuery {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::element::H;
+        use crate::builtins::element::HComment;
+
+        let _ = H {}.set_function(lua);
+        let _ = HComment {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::markdown::HtmlToMarkdown;
+        use crate::builtins::markdown::MarkdownToHtml;
+
+        let _ = HtmlToMarkdown {}.set_function(lua);
+        let _ = MarkdownToHtml {}.set_function(lua);
     }
 
     {
@@ -22,6 +98,47 @@ pub fn init(lua: &Lua) -> mlua::Result<()> {
         let _ = ShiftJis {}.set_function(lua);
     }
 
+    {
+        use crate::builtins::encoding::Decode;
+        use crate::builtins::encoding::Encode;
+
+        let _ = Encode {}.set_function(lua);
+        let _ = Decode {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::normalize::Normalize;
+        use crate::builtins::normalize::StripInvisible;
+        use crate::builtins::normalize::ToFullwidth;
+        use crate::builtins::normalize::ToHalfwidth;
+        use crate::builtins::normalize::ToHiragana;
+        use crate::builtins::normalize::ToKatakana;
+
+        let _ = Normalize {}.set_function(lua);
+        let _ = ToHalfwidth {}.set_function(lua);
+        let _ = ToFullwidth {}.set_function(lua);
+        let _ = ToKatakana {}.set_function(lua);
+        let _ = ToHiragana {}.set_function(lua);
+        let _ = StripInvisible {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::width::DisplayWidth;
+        use crate::builtins::width::FormatFixedColumns;
+        use crate::builtins::width::Pad;
+        use crate::builtins::width::Truncate;
+
+        let _ = DisplayWidth {}.set_function(lua);
+        let _ = Pad {}.set_function(lua);
+        let _ = Truncate {}.set_function(lua);
+        let _ = FormatFixedColumns {}.set_function(lua);
+    }
+
+    {
+        use crate::builtins::re::Re;
+        let _ = Re {}.set_table(lua);
+    }
+
     {
         use crate::builtins::include::Include;
         let _ = Include {}.set_function(lua);
@@ -0,0 +1,278 @@
+//! CSV / TSV conversion
+//!
+//! # Example
+//! ```lua
+//! -- a range copied from Excel or Google Sheets is detected as TSV
+//! local rows = csv_to_table(qlp.text, { header = true })
+//! for _, row in ipairs(rows) do
+//!     print(row.Name, row[2])
+//! end
+//!
+//! qlp.result = table_to_csv(rows, { header = true, terminator = "\r\n" })
+//! ```
+
+use csv::{QuoteStyle, ReaderBuilder, Terminator, WriterBuilder};
+use mlua::{Function, Lua, Table, Value};
+
+use crate::utils::{lua_table_rows, lua_value_to_text};
+
+use super::builtin::BuiltinFunction;
+
+pub struct CsvToTable;
+
+impl BuiltinFunction for CsvToTable {
+    fn get_name(&self) -> &str {
+        "csv_to_table"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, (text, opts): (String, Option<Table>)| {
+            csv_to_table(lua, &text, opts)
+        })
+        .unwrap()
+    }
+}
+
+pub struct TableToCsv;
+
+impl BuiltinFunction for TableToCsv {
+    fn get_name(&self) -> &str {
+        "table_to_csv"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (table, opts): (Table, Option<Table>)| table_to_csv(&table, opts))
+            .unwrap()
+    }
+}
+
+fn csv_error(e: csv::Error) -> mlua::Error {
+    mlua::Error::RuntimeError(format!("invalid CSV: {}", e))
+}
+
+/// Reads a single-byte option such as `delimiter = "\t"`.
+fn get_byte(opts: &Option<Table>, key: &str) -> mlua::Result<Option<u8>> {
+    let Some(opts) = opts else {
+        return Ok(None);
+    };
+
+    match opts.get::<Option<String>>(key)? {
+        None => Ok(None),
+        Some(s) if s.len() == 1 => Ok(Some(s.as_bytes()[0])),
+        Some(s) => Err(mlua::Error::RuntimeError(format!(
+            "{} must be a single ASCII character: {:?}",
+            key, s
+        ))),
+    }
+}
+
+/// Guesses the delimiter from the first record, spreadsheets copy cells separated by tabs.
+fn detect_delimiter(text: &str) -> u8 {
+    let mut in_quotes = false;
+    for c in text.chars() {
+        match c {
+            '"' => in_quotes = !in_quotes,
+            '\t' if !in_quotes => return b'\t',
+            '\n' if !in_quotes => break,
+            _ => {}
+        }
+    }
+
+    b','
+}
+
+fn csv_to_table(lua: &Lua, text: &str, opts: Option<Table>) -> mlua::Result<Table> {
+    let delimiter = get_byte(&opts, "delimiter")?.unwrap_or_else(|| detect_delimiter(text));
+    let quote = get_byte(&opts, "quote")?.unwrap_or(b'"');
+    let escape = get_byte(&opts, "escape")?;
+    let header = match &opts {
+        Some(opts) => opts.get::<Option<bool>>("header")?.unwrap_or(false),
+        None => false,
+    };
+
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .escape(escape)
+        // RFC 4180 doubles quotes unless an escape character is given
+        .double_quote(escape.is_none())
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let rows = lua.create_table()?;
+    let mut headers: Option<Vec<String>> = None;
+
+    for record in reader.records() {
+        let record = record.map_err(csv_error)?;
+
+        if header && headers.is_none() {
+            headers = Some(record.iter().map(|f| f.to_string()).collect());
+            continue;
+        }
+
+        let row = lua.create_sequence_from(record.iter())?;
+        if let Some(headers) = &headers {
+            for (name, field) in headers.iter().zip(record.iter()) {
+                if !name.is_empty() {
+                    row.set(name.as_str(), field)?;
+                }
+            }
+        }
+
+        rows.push(row)?;
+    }
+
+    if let Some(headers) = headers {
+        rows.set("headers", headers)?;
+    }
+
+    Ok(rows)
+}
+
+fn get_quote_style(opts: &Option<Table>) -> mlua::Result<QuoteStyle> {
+    let style = match opts {
+        Some(opts) => opts.get::<Option<String>>("quote_style")?,
+        None => None,
+    };
+
+    match style.as_deref() {
+        None | Some("necessary") => Ok(QuoteStyle::Necessary),
+        Some("always") => Ok(QuoteStyle::Always),
+        Some("non_numeric") => Ok(QuoteStyle::NonNumeric),
+        Some("never") => Ok(QuoteStyle::Never),
+        Some(s) => Err(mlua::Error::RuntimeError(format!(
+            "unknown quote_style: {}",
+            s
+        ))),
+    }
+}
+
+fn get_terminator(opts: &Option<Table>) -> mlua::Result<Terminator> {
+    let terminator = match opts {
+        Some(opts) => opts.get::<Option<String>>("terminator")?,
+        None => None,
+    };
+
+    match terminator.as_deref() {
+        None => Ok(Terminator::Any(b'\n')),
+        Some("\r\n") => Ok(Terminator::CRLF),
+        Some(s) if s.len() == 1 => Ok(Terminator::Any(s.as_bytes()[0])),
+        Some(s) => Err(mlua::Error::RuntimeError(format!(
+            "terminator must be \"\\r\\n\" or a single ASCII character: {:?}",
+            s
+        ))),
+    }
+}
+
+/// Column names for the header row, `header = true` takes them from `headers` of the table.
+fn get_header(table: &Table, opts: &Option<Table>) -> mlua::Result<Option<Vec<String>>> {
+    let Some(opts) = opts else {
+        return Ok(None);
+    };
+
+    match opts.get::<Value>("header")? {
+        Value::Nil | Value::Boolean(false) => Ok(None),
+        Value::Boolean(true) => table.get::<Option<Vec<String>>>("headers"),
+        Value::Table(t) => Ok(Some(
+            t.sequence_values::<Value>()
+                .map(|v| lua_value_to_text(v?))
+                .collect::<mlua::Result<Vec<_>>>()?,
+        )),
+        _ => Err(mlua::Error::RuntimeError(
+            "header must be a boolean or a list of column names".to_string(),
+        )),
+    }
+}
+
+fn table_to_csv(table: &Table, opts: Option<Table>) -> mlua::Result<String> {
+    let delimiter = get_byte(&opts, "delimiter")?.unwrap_or(b',');
+    let quote = get_byte(&opts, "quote")?.unwrap_or(b'"');
+    let escape = get_byte(&opts, "escape")?;
+    let header = get_header(table, &opts)?;
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .quote(quote)
+        .escape(escape.unwrap_or(b'\\'))
+        .double_quote(escape.is_none())
+        .quote_style(get_quote_style(&opts)?)
+        .terminator(get_terminator(&opts)?)
+        .flexible(true)
+        .from_writer(vec![]);
+
+    if let Some(header) = &header {
+        writer.write_record(header).map_err(csv_error)?;
+    }
+
+    let rows = lua_table_rows(table)?;
+
+    for row in rows.sequence_values::<Value>() {
+        let fields = match row? {
+            // a row keyed by column names only
+            Value::Table(row) if row.raw_len() == 0 && header.is_some() => header
+                .iter()
+                .flatten()
+                .map(|name| lua_value_to_text(row.get::<Value>(name.as_str())?))
+                .collect::<mlua::Result<Vec<_>>>()?,
+            Value::Table(row) => row
+                .sequence_values::<Value>()
+                .map(|v| lua_value_to_text(v?))
+                .collect::<mlua::Result<Vec<_>>>()?,
+            value => vec![lua_value_to_text(value)?],
+        };
+
+        writer.write_record(&fields).map_err(csv_error)?;
+    }
+
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+
+    String::from_utf8(bytes).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_delimiter() {
+        assert_eq!(b'\t', detect_delimiter("a, b\tc\r\n1\t2\r\n"));
+        assert_eq!(b',', detect_delimiter("a,\"b\tc\"\n1\t2"));
+        assert_eq!(b',', detect_delimiter("a,b"));
+    }
+
+    #[test]
+    fn test_csv() {
+        let lua = Lua::new();
+        CsvToTable {}.set_function(&lua).unwrap();
+        TableToCsv {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            -- spreadsheet copy, a cell with a line break is quoted
+            local rows = csv_to_table('Name\tNote\r\nfoo\t"two\r\nlines"\r\nbar\t\r\n', { header = true })
+            assert(#rows == 2)
+            assert(rows.headers[2] == "Note")
+            assert(rows[1].Note == "two\r\nlines" and rows[1][2] == "two\r\nlines")
+            assert(rows[2].Name == "bar" and rows[2].Note == "")
+
+            local csv = csv_to_table('a,"b ""c"""\n1,2,3\n')
+            assert(#csv == 2 and csv[1][2] == 'b "c"' and #csv[2] == 3)
+
+            local escaped = csv_to_table('a;"b \\"c\\""', { delimiter = ";", escape = "\\" })
+            assert(escaped[1][2] == 'b "c"')
+
+            assert(table_to_csv({ { "a", 'b "c"' }, { 1, true, "x,y" } }) == 'a,"b ""c"""\n1,true,"x,y"\n')
+            assert(table_to_csv(rows, { header = true, delimiter = "\t", terminator = "\r\n" })
+                == 'Name\tNote\r\nfoo\t"two\r\nlines"\r\nbar\t\r\n')
+            assert(table_to_csv({ { Count = 1, Name = "foo" } }, { header = { "Name", "Count" }, quote_style = "always" })
+                == '"Name","Count"\n"foo","1"\n')
+            assert(not pcall(csv_to_table, "a", { delimiter = "ab" }))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
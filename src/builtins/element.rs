@@ -0,0 +1,110 @@
+//! HTML element builder
+//!
+//! # Example
+//! ```lua
+//! qlp.result = h("p", { class = "note" }, {
+//!     "see ",
+//!     h("a", { href = "https://example.com/" }, "example"),
+//!     h_comment("generated"),
+//! })
+//! ```
+
+use markup5ever_rcdom::Handle;
+use mlua::{Function, Lua, Value};
+
+use crate::{
+    dom::LuaNode,
+    html::{append_child, create_comment, create_element, create_text, lua_value_to_attrs},
+};
+
+use super::builtin::BuiltinFunction;
+
+pub struct H;
+
+impl BuiltinFunction for H {
+    fn get_name(&self) -> &str {
+        "h"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (tag, attrs, children): (String, Value, Value)| {
+            // h(tag, children) without attributes
+            let (attrs, children) = match (&attrs, &children) {
+                (Value::Table(t), Value::Nil) if t.raw_len() > 0 => (Value::Nil, attrs),
+                (Value::Table(_) | Value::Nil, _) => (attrs, children),
+                (_, Value::Nil) => (Value::Nil, attrs),
+                _ => (attrs, children),
+            };
+
+            let element = create_element(tag, lua_value_to_attrs(&attrs)?);
+            for child in to_children(children)? {
+                append_child(&element, child);
+            }
+
+            Ok(LuaNode(element))
+        })
+        .unwrap()
+    }
+}
+
+pub struct HComment;
+
+impl BuiltinFunction for HComment {
+    fn get_name(&self) -> &str {
+        "h_comment"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| Ok(LuaNode(create_comment(text))))
+            .unwrap()
+    }
+}
+
+fn to_children(value: Value) -> mlua::Result<Vec<Handle>> {
+    match value {
+        // `cond and h(...)` leaves false
+        Value::Nil | Value::Boolean(false) => Ok(vec![]),
+        Value::String(s) => Ok(vec![create_text(s.to_str()?.to_string())]),
+        Value::Integer(n) => Ok(vec![create_text(n)]),
+        Value::Number(n) => Ok(vec![create_text(n)]),
+        Value::UserData(ud) => Ok(vec![ud.borrow::<LuaNode>()?.0.clone()]),
+        Value::Table(t) => {
+            let mut children = Vec::new();
+            for child in t.sequence_values::<Value>() {
+                children.extend(to_children(child?)?);
+            }
+            Ok(children)
+        }
+        _ => Err(mlua::Error::RuntimeError(
+            "children must be strings, numbers, nodes or a list of them".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_h() {
+        let lua = Lua::new();
+        H {}.set_function(&lua).unwrap();
+        HComment {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local link = h("a", { href = "https://example.com/", class = "badge", hidden = false }, "example")
+            local p = h("P", { id = "note" }, { "see ", link, 1, h_comment("generated"), false })
+            assert(tostring(p) == '<p id="note">see <a class="badge" href="https://example.com/">example</a>1<!--generated--></p>')
+            assert(link.parent == p)
+
+            assert(tostring(h("pre", { h("code", "x < y") })) == "<pre><code>x &lt; y</code></pre>")
+            assert(tostring(h("br")) == "<br>")
+            assert(tostring(h("input", { checked = true })) == '<input checked="">')
+            assert(not pcall(h, "p", { style = {} }))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
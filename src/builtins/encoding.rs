@@ -0,0 +1,433 @@
+//! Conversion from / to legacy encodings
+//!
+//! # Example
+//! ```lua
+//! -- a file from a partner in EUC-JP, read as bytes since include() only reads UTF-8
+//! local file = io.open("orders.csv", "rb")
+//! local text = decode(file:read("a"), "euc-jp")
+//! file:close()
+//!
+//! -- characters not in the encoding are reported
+//! local bytes, unmappable = encode(text, "shift_jis", { on_error = "replace" })
+//! for _, u in ipairs(unmappable) do
+//!     print(string.format("%d:%d %s", u.line, u.column, u.char))
+//! end
+//! ```
+
+use encoding_rs::{DecoderResult, EncoderResult, Encoding, UTF_16BE, UTF_16LE};
+use mlua::{Function, Lua, Table};
+
+use super::builtin::BuiltinFunction;
+
+/// What to do with characters (or bytes) the encoding has no mapping for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OnError {
+    Error,
+    Replace(String),
+    /// HTML numeric character references such as `&#134071;`
+    Ncr,
+    Ignore,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unmappable {
+    pub c: char,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Malformed {
+    /// from 1
+    pub offset: usize,
+    pub bytes: Vec<u8>,
+}
+
+/// Finds an encoding by its WHATWG label, also Windows code page names.
+pub fn get_encoding(label: &str) -> mlua::Result<&'static Encoding> {
+    let label = label.trim().to_ascii_lowercase();
+    let label = match label.as_str() {
+        "cp932" | "ms932" => "shift_jis",
+        "cp936" => "gbk",
+        "cp949" => "euc-kr",
+        "cp950" => "big5",
+        "cp51932" | "eucjp" => "euc-jp",
+        "jis" => "iso-2022-jp",
+        "utf16le" => "utf-16le",
+        "utf16be" => "utf-16be",
+        label => label,
+    };
+
+    Encoding::for_label(label.as_bytes())
+        .ok_or_else(|| mlua::Error::RuntimeError(format!("unknown encoding: {}", label)))
+}
+
+fn get_on_error(opts: &Option<Table>, replacement: &str) -> mlua::Result<OnError> {
+    let Some(opts) = opts else {
+        return Ok(OnError::Error);
+    };
+
+    match opts.get::<Option<String>>("on_error")?.as_deref() {
+        None | Some("error") => Ok(OnError::Error),
+        Some("replace") => Ok(OnError::Replace(
+            opts.get::<Option<String>>("replacement")?
+                .unwrap_or_else(|| replacement.to_string()),
+        )),
+        Some("ncr") => Ok(OnError::Ncr),
+        Some("ignore") => Ok(OnError::Ignore),
+        Some(s) => Err(mlua::Error::RuntimeError(format!(
+            "on_error must be error, replace, ncr or ignore: {}",
+            s
+        ))),
+    }
+}
+
+/// Line and column, from 1, of characters met in order, counting only the text between them.
+struct Position<'a> {
+    text: &'a str,
+    counted: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> Position<'a> {
+    fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            counted: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+
+    /// The position of the character `c` ending at `end`.
+    fn of(&mut self, end: usize, c: char) -> (usize, usize) {
+        for ch in self.text[self.counted..end - c.len_utf8()].chars() {
+            if ch == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
+        let position = (self.line, self.column);
+
+        // line breaks are in every encoding, so `c` is not one
+        self.column += 1;
+        self.counted = end;
+
+        position
+    }
+}
+
+/// Encodes `text`, returning the bytes and the characters that were not encoded as themselves.
+pub fn encode(
+    text: &str,
+    encoding: &'static Encoding,
+    on_error: &OnError,
+) -> mlua::Result<(Vec<u8>, Vec<Unmappable>)> {
+    // encoding_rs writes UTF-8 for UTF-16 as browsers do, but files want UTF-16
+    if encoding == UTF_16LE {
+        return Ok((
+            text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+            vec![],
+        ));
+    }
+    if encoding == UTF_16BE {
+        return Ok((
+            text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            vec![],
+        ));
+    }
+
+    let mut encoder = encoding.new_encoder();
+    let mut out = Vec::with_capacity(text.len());
+    let mut buf = vec![0u8; 4096];
+    let mut unmappable = Vec::new();
+    let mut position = Position::new(text);
+    let mut rest = text;
+
+    loop {
+        let (result, read, written) =
+            encoder.encode_from_utf8_without_replacement(rest, &mut buf, true);
+        out.extend_from_slice(&buf[..written]);
+        rest = &rest[read..];
+
+        match result {
+            EncoderResult::InputEmpty => break,
+            EncoderResult::OutputFull => {}
+            EncoderResult::Unmappable(c) => {
+                let (line, column) = position.of(text.len() - rest.len(), c);
+                match on_error {
+                    OnError::Error => {
+                        return Err(mlua::Error::RuntimeError(format!(
+                            "{:?} (U+{:04X}) at line {}, column {} cannot be encoded in {}",
+                            c,
+                            c as u32,
+                            line,
+                            column,
+                            encoding.name()
+                        )));
+                    }
+                    OnError::Replace(replacement) => {
+                        out.extend_from_slice(&encoding.encode(replacement).0)
+                    }
+                    OnError::Ncr => out.extend_from_slice(format!("&#{};", c as u32).as_bytes()),
+                    OnError::Ignore => {}
+                }
+                unmappable.push(Unmappable { c, line, column });
+            }
+        }
+    }
+
+    Ok((out, unmappable))
+}
+
+/// Decodes `bytes`, a BOM of the encoding is removed.
+pub fn decode(
+    bytes: &[u8],
+    encoding: &'static Encoding,
+    on_error: &OnError,
+) -> mlua::Result<(String, Vec<Malformed>)> {
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut out = String::with_capacity(
+        decoder
+            .max_utf8_buffer_length_without_replacement(bytes.len())
+            .unwrap_or(bytes.len()),
+    );
+    let mut malformed = Vec::new();
+    let mut pos = 0;
+
+    loop {
+        let (result, read) =
+            decoder.decode_to_string_without_replacement(&bytes[pos..], &mut out, true);
+        pos += read;
+
+        match result {
+            DecoderResult::InputEmpty => break,
+            DecoderResult::OutputFull => out.reserve(
+                decoder
+                    .max_utf8_buffer_length_without_replacement(bytes.len() - pos)
+                    .unwrap_or(bytes.len() - pos)
+                    .max(4),
+            ),
+            DecoderResult::Malformed(len, consumed) => {
+                let start = pos - consumed as usize - len as usize;
+                let bad = bytes[start..start + len as usize].to_vec();
+                match on_error {
+                    // references are for characters missing in an encoding
+                    OnError::Error | OnError::Ncr => {
+                        return Err(mlua::Error::RuntimeError(format!(
+                            "invalid {} bytes {} at offset {}",
+                            encoding.name(),
+                            bad.iter()
+                                .map(|b| format!("{:02X}", b))
+                                .collect::<Vec<_>>()
+                                .join(" "),
+                            start + 1
+                        )));
+                    }
+                    OnError::Replace(replacement) => out.push_str(replacement),
+                    OnError::Ignore => {}
+                }
+                malformed.push(Malformed {
+                    offset: start + 1,
+                    bytes: bad,
+                });
+            }
+        }
+    }
+
+    Ok((out, malformed))
+}
+
+pub struct Encode;
+
+impl BuiltinFunction for Encode {
+    fn get_name(&self) -> &str {
+        "encode"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(
+            |lua, (text, label, opts): (String, String, Option<Table>)| {
+                let (bytes, unmappable) =
+                    encode(&text, get_encoding(&label)?, &get_on_error(&opts, "?")?)?;
+
+                let errors = lua.create_table()?;
+                for u in unmappable {
+                    let error = lua.create_table()?;
+                    error.set("char", u.c.to_string())?;
+                    error.set("line", u.line)?;
+                    error.set("column", u.column)?;
+                    errors.push(error)?;
+                }
+
+                Ok((lua.create_string(&bytes)?, errors))
+            },
+        )
+        .unwrap()
+    }
+}
+
+pub struct Decode;
+
+impl BuiltinFunction for Decode {
+    fn get_name(&self) -> &str {
+        "decode"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(
+            |lua, (bytes, label, opts): (mlua::String, String, Option<Table>)| {
+                let (text, malformed) = decode(
+                    &bytes.as_bytes(),
+                    get_encoding(&label)?,
+                    &get_on_error(&opts, "\u{FFFD}")?,
+                )?;
+
+                let errors = lua.create_table()?;
+                for m in malformed {
+                    let error = lua.create_table()?;
+                    error.set("offset", m.offset)?;
+                    error.set("bytes", lua.create_string(&m.bytes)?)?;
+                    errors.push(error)?;
+                }
+
+                Ok((text, errors))
+            },
+        )
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode() {
+        let sjis = get_encoding("cp932").unwrap();
+        assert_eq!(
+            (vec![0x82, 0xA0, b'a'], vec![]),
+            encode("あa", sjis, &OnError::Error).unwrap()
+        );
+
+        let err = encode("a\nb𠮷", sjis, &OnError::Error).unwrap_err();
+        assert!(err.to_string().contains("at line 2, column 2"));
+
+        let (bytes, unmappable) =
+            encode("a\nb𠮷c", sjis, &OnError::Replace("?".to_string())).unwrap();
+        assert_eq!(b"a\nb?c".to_vec(), bytes);
+        assert_eq!(
+            vec![Unmappable {
+                c: '𠮷',
+                line: 2,
+                column: 2
+            }],
+            unmappable
+        );
+        assert_eq!(
+            b"&#134071;".to_vec(),
+            encode("𠮷", sjis, &OnError::Ncr).unwrap().0
+        );
+        let (_, unmappable) = encode("𠮷\n😀x𠮷", sjis, &OnError::Ignore).unwrap();
+        assert_eq!(
+            vec![(1, 1), (2, 1), (2, 3)],
+            unmappable
+                .iter()
+                .map(|u| (u.line, u.column))
+                .collect::<Vec<_>>()
+        );
+
+        // escapes back to ASCII around the replacement
+        let jis = get_encoding("iso-2022-jp").unwrap();
+        let (bytes, _) = encode("あ𠮷", jis, &OnError::Replace("?".to_string())).unwrap();
+        assert_eq!(b"\x1b$B$\"\x1b(B?".to_vec(), bytes);
+
+        assert_eq!(
+            vec![0x42, 0x30, b'a', 0],
+            encode("あa", get_encoding("utf-16le").unwrap(), &OnError::Error)
+                .unwrap()
+                .0
+        );
+        assert_eq!(
+            vec![0x30, 0x42],
+            encode("あ", get_encoding("UTF-16BE").unwrap(), &OnError::Error)
+                .unwrap()
+                .0
+        );
+        assert!(get_encoding("x-unknown").is_err());
+    }
+
+    #[test]
+    fn test_decode() {
+        let eucjp = get_encoding("euc-jp").unwrap();
+        assert_eq!(
+            ("あa".to_string(), vec![]),
+            decode(&[0xA4, 0xA2, b'a'], eucjp, &OnError::Error).unwrap()
+        );
+
+        let err = decode(&[b'a', 0xA4], eucjp, &OnError::Error).unwrap_err();
+        assert!(err.to_string().contains("A4 at offset 2"));
+
+        let (text, malformed) = decode(
+            &[b'a', 0xFF, b'b'],
+            eucjp,
+            &OnError::Replace("\u{FFFD}".to_string()),
+        )
+        .unwrap();
+        assert_eq!("a\u{FFFD}b", text);
+        assert_eq!(
+            vec![Malformed {
+                offset: 2,
+                bytes: vec![0xFF]
+            }],
+            malformed
+        );
+
+        // the BOM is removed
+        assert_eq!(
+            "あ",
+            decode(
+                &[0xFF, 0xFE, 0x42, 0x30],
+                get_encoding("utf-16le").unwrap(),
+                &OnError::Error
+            )
+            .unwrap()
+            .0
+        );
+        assert_eq!(
+            "€",
+            decode(
+                &[0x80],
+                get_encoding("windows-1252").unwrap(),
+                &OnError::Error
+            )
+            .unwrap()
+            .0
+        );
+    }
+
+    #[test]
+    fn test_encode_decode_builtins() {
+        let lua = Lua::new();
+        Encode {}.set_function(&lua).unwrap();
+        Decode {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local bytes, errors = encode("漢字 ☃", "gbk", { on_error = "replace", replacement = "*" })
+            assert(#errors == 1 and errors[1].char == "☃" and errors[1].column == 4)
+            assert(decode(bytes, "gbk") == "漢字 *")
+            assert(decode(encode("中文", "big5"), "big5") == "中文")
+            assert(not pcall(encode, "☃", "shift_jis"))
+
+            local text, bad = decode("a\xFFb", "shift_jis", { on_error = "replace" })
+            assert(text == "a\u{FFFD}b" and bad[1].offset == 2 and bad[1].bytes == "\xFF")
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
@@ -1,6 +1,7 @@
-use mlua::{Function, Lua};
+use mlua::{Function, Lua, Table, Value};
+use serde::Serialize;
 
-use crate::utils::json_str_to_lua_table;
+use crate::utils::{json_null, json_str_to_lua_table, lua_to_json, set_json_type, sort_json_keys};
 
 use super::builtin::BuiltinFunction;
 
@@ -54,3 +55,159 @@ impl BuiltinFunction for JsonToTable {
             .unwrap()
     }
 }
+
+pub struct TableToJson;
+impl BuiltinFunction for TableToJson {
+    fn get_name(&self) -> &str {
+        "table_to_json"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, (value, opts): (Value, Option<Table>)| {
+            table_to_json(lua, value, opts)
+        })
+        .unwrap()
+    }
+}
+
+fn table_to_json(lua: &Lua, value: Value, opts: Option<Table>) -> mlua::Result<String> {
+    let opts = match opts {
+        Some(opts) => opts,
+        None => lua.create_table()?,
+    };
+
+    // an indent implies pretty printing
+    let indent = match opts.get::<Value>("indent")? {
+        Value::Nil => None,
+        Value::Integer(n) if n >= 0 => Some(" ".repeat(n as usize)),
+        Value::String(s) => Some(s.to_str()?.to_string()),
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "indent must be a number of spaces or a string".to_string(),
+            ));
+        }
+    };
+    let pretty = opts
+        .get::<Option<bool>>("pretty")?
+        .unwrap_or(indent.is_some());
+
+    let mut json = lua_to_json(lua, value)?;
+    if opts.get::<Option<bool>>("sort_keys")?.unwrap_or(false) {
+        sort_json_keys(&mut json);
+    }
+
+    if !pretty {
+        return serde_json::to_string(&json).map_err(|e| mlua::Error::RuntimeError(e.to_string()));
+    }
+
+    let indent = indent.unwrap_or_else(|| "  ".to_string());
+    let mut buf = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(indent.as_bytes());
+    let mut serializer = serde_json::Serializer::with_formatter(&mut buf, formatter);
+    json.serialize(&mut serializer)
+        .map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+
+    String::from_utf8(buf).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+}
+
+pub struct JsonArray;
+impl BuiltinFunction for JsonArray {
+    fn get_name(&self) -> &str {
+        "json_array"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, table: Option<Table>| json_typed_table(lua, table, "array"))
+            .unwrap()
+    }
+}
+
+pub struct JsonObject;
+impl BuiltinFunction for JsonObject {
+    fn get_name(&self) -> &str {
+        "json_object"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, table: Option<Table>| json_typed_table(lua, table, "object"))
+            .unwrap()
+    }
+}
+
+pub struct JsonNull;
+
+impl JsonNull {
+    /// Sets `json_null`, the `null` of tables from and to JSON, YAML and TOML.
+    pub fn set_value(&self, lua: &Lua) -> mlua::Result<()> {
+        lua.globals().set("json_null", json_null())
+    }
+}
+
+fn json_typed_table(lua: &Lua, table: Option<Table>, json_type: &str) -> mlua::Result<Table> {
+    let table = match table {
+        Some(table) => table,
+        None => lua.create_table()?,
+    };
+    set_json_type(lua, &table, json_type)?;
+
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_table_to_json() {
+        let lua = Lua::new();
+        JsonToTable {}.set_function(&lua).unwrap();
+        TableToJson {}.set_function(&lua).unwrap();
+        JsonArray {}.set_function(&lua).unwrap();
+        JsonObject {}.set_function(&lua).unwrap();
+        JsonNull {}.set_value(&lua).unwrap();
+
+        lua.load(
+            r#"
+            -- key order and empty tables survive a round trip
+            local t = json_to_table('{"name":"qlp","tags":[],"meta":{},"z":1,"a":2}')
+            t.added = true
+            t.z = nil
+            assert(table_to_json(t) == '{"name":"qlp","tags":[],"meta":{},"a":2,"added":true}')
+            assert(table_to_json(t, { sort_keys = true }) == '{"a":2,"added":true,"meta":{},"name":"qlp","tags":[]}')
+            t.name = nil
+            t.name = "moved"
+            assert(table_to_json(t) == '{"tags":[],"meta":{},"a":2,"added":true,"name":"moved"}')
+
+            assert(table_to_json({ 1, 2 }, { pretty = true }) == "[\n  1,\n  2\n]")
+            assert(table_to_json({ a = { 1 } }, { indent = "\t" }) == '{\n\t"a": [\n\t\t1\n\t]\n}')
+
+            -- unmarked tables: sparse arrays get nulls, other keys make objects in name order
+            assert(table_to_json({ [1] = "a", [3] = "c" }) == '["a",null,"c"]')
+            assert(table_to_json({ b = 1, a = 2, [1] = 3 }) == '{"1":3,"a":2,"b":1}')
+            assert(table_to_json({}) == "[]")
+
+            -- arrays too sparse to write
+            local sparse = json_array({})
+            sparse[1e9] = true
+            assert(not pcall(table_to_json, sparse))
+
+            -- nulls are json_null, so that keys and places are kept
+            local nulls = json_to_table('{"a":null,"b":[null,null,null,1]}')
+            assert(nulls.a == json_null and nulls.b[1] == json_null)
+            assert(table_to_json(nulls) == '{"a":null,"b":[null,null,null,1]}')
+            nulls.c = json_null
+            assert(table_to_json(nulls) == '{"a":null,"b":[null,null,null,1],"c":null}')
+
+            local obj = json_object()
+            assert(table_to_json(obj) == "{}")
+            obj.second = 2
+            obj.first = 1
+            assert(table_to_json(obj) == '{"second":2,"first":1}')
+            assert(table_to_json({ list = json_array({}) }) == '{"list":[]}')
+            assert(not pcall(table_to_json, { f = print }))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
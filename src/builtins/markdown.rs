@@ -0,0 +1,39 @@
+//! Markdown conversion commands
+//!
+//! # Example
+//! ```lua
+//! qlp.result = html_to_markdown(qlp.fragment)
+//! qlp.result_html_raw = markdown_to_html("# Report")
+//! ```
+
+use mlua::{Function, Lua};
+
+use crate::markdown::{html_to_markdown, markdown_to_html};
+
+use super::builtin::BuiltinFunction;
+
+pub struct HtmlToMarkdown;
+
+impl BuiltinFunction for HtmlToMarkdown {
+    fn get_name(&self) -> &str {
+        "html_to_markdown"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, html: String| Ok(html_to_markdown(&html)))
+            .unwrap()
+    }
+}
+
+pub struct MarkdownToHtml;
+
+impl BuiltinFunction for MarkdownToHtml {
+    fn get_name(&self) -> &str {
+        "markdown_to_html"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, markdown: String| Ok(markdown_to_html(&markdown)))
+            .unwrap()
+    }
+}
@@ -1,6 +1,16 @@
 pub mod builtin;
 
+pub mod csv;
+pub mod element;
+pub mod encoding;
 pub mod exec;
 pub mod include;
 pub mod json;
+pub mod markdown;
+pub mod normalize;
+pub mod re;
 pub mod s;
+pub mod toml;
+pub mod width;
+pub mod xml;
+pub mod yaml;
@@ -0,0 +1,393 @@
+//! Text normalization, Unicode forms, full-width / half-width and kana
+//!
+//! # Example
+//! ```lua
+//! -- a pasted address: "ﾄｳｷｮｳﾄ　千代田区１－２－３\u{200B}"
+//! local address = strip_invisible(qlp.text)
+//! address = to_halfwidth(address, { "alnum", "symbol", "space" })
+//! address = to_fullwidth(address, { "katakana" })
+//! qlp.result = address -- "トウキョウト 千代田区1-2-3"
+//! ```
+
+use mlua::{Function, Lua, Table};
+use unicode_normalization::{
+    UnicodeNormalization,
+    char::{compose, decompose_canonical},
+};
+
+use super::builtin::BuiltinFunction;
+
+const HALFWIDTH_KATAKANA: &str = "｡｢｣､･ｦｧｨｩｪｫｬｭｮｯｰｱｲｳｴｵｶｷｸｹｺｻｼｽｾｿﾀﾁﾂﾃﾄﾅﾆﾇﾈﾉﾊﾋﾌﾍﾎﾏﾐﾑﾒﾓﾔﾕﾖﾗﾘﾙﾚﾛﾜﾝﾞﾟ";
+const FULLWIDTH_KATAKANA: &str = "。「」、・ヲァィゥェォャュョッーアイウエオカキクケコサシスセソタチツテトナニヌネノハヒフヘホマミムメモヤユヨラリルレロワン゛゜";
+
+const VOICED_MARK: char = '\u{3099}';
+const SEMI_VOICED_MARK: char = '\u{309A}';
+
+/// Characters converted by `to_halfwidth` and `to_fullwidth`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Ranges {
+    /// digits and Latin letters
+    pub alnum: bool,
+    /// the other ASCII characters
+    pub symbol: bool,
+    /// the ideographic space
+    pub space: bool,
+    /// katakana with their punctuation and voiced marks
+    pub katakana: bool,
+}
+
+impl Ranges {
+    pub fn all() -> Self {
+        Self {
+            alnum: true,
+            symbol: true,
+            space: true,
+            katakana: true,
+        }
+    }
+
+    fn from_lua(ranges: Option<Table>) -> mlua::Result<Self> {
+        let Some(ranges) = ranges else {
+            return Ok(Self::all());
+        };
+
+        let mut selected = Self::default();
+        for name in ranges.sequence_values::<String>() {
+            match name?.as_str() {
+                "alnum" => selected.alnum = true,
+                "symbol" => selected.symbol = true,
+                "space" => selected.space = true,
+                "katakana" => selected.katakana = true,
+                name => {
+                    return Err(mlua::Error::RuntimeError(format!(
+                        "unknown range: {} (alnum, symbol, space or katakana)",
+                        name
+                    )));
+                }
+            }
+        }
+
+        Ok(selected)
+    }
+
+    /// Whether the ASCII character `c` is in the ranges.
+    fn has_ascii(&self, c: char) -> bool {
+        match c {
+            ' ' => self.space,
+            c if c.is_ascii_alphanumeric() => self.alnum,
+            c if c.is_ascii_graphic() => self.symbol,
+            _ => false,
+        }
+    }
+}
+
+fn katakana_to_halfwidth(c: char) -> Option<char> {
+    FULLWIDTH_KATAKANA
+        .chars()
+        .position(|k| k == c)
+        .and_then(|i| HALFWIDTH_KATAKANA.chars().nth(i))
+}
+
+fn katakana_to_fullwidth(c: char) -> Option<char> {
+    HALFWIDTH_KATAKANA
+        .chars()
+        .position(|k| k == c)
+        .and_then(|i| FULLWIDTH_KATAKANA.chars().nth(i))
+}
+
+pub fn to_halfwidth(text: &str, ranges: Ranges) -> String {
+    let mut out = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '\u{3000}' if ranges.space => out.push(' '),
+            '\u{FF01}'..='\u{FF5E}' => {
+                let ascii = char::from_u32(c as u32 - 0xFEE0).unwrap();
+                out.push(if ranges.has_ascii(ascii) { ascii } else { c });
+            }
+            _ if ranges.katakana => {
+                if let Some(half) = katakana_to_halfwidth(c) {
+                    out.push(half);
+                    continue;
+                }
+
+                // ガ is カ and a voiced mark
+                let mut parts = Vec::new();
+                decompose_canonical(c, |d| parts.push(d));
+                match parts.as_slice() {
+                    [base, mark @ (VOICED_MARK | SEMI_VOICED_MARK)]
+                        if katakana_to_halfwidth(*base).is_some() =>
+                    {
+                        out.push(katakana_to_halfwidth(*base).unwrap());
+                        out.push(if *mark == VOICED_MARK { 'ﾞ' } else { 'ﾟ' });
+                    }
+                    _ => out.push(c),
+                }
+            }
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+pub fn to_fullwidth(text: &str, ranges: Ranges) -> String {
+    let mut out = String::with_capacity(text.len() * 3);
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c.is_ascii() && ranges.has_ascii(c) {
+            out.push(match c {
+                ' ' => '\u{3000}',
+                c => char::from_u32(c as u32 + 0xFEE0).unwrap(),
+            });
+            continue;
+        }
+
+        let full = match katakana_to_fullwidth(c) {
+            Some(full) if ranges.katakana => full,
+            _ => {
+                out.push(c);
+                continue;
+            }
+        };
+
+        // ｶﾞ becomes ガ where such a character exists
+        let mark = match chars.peek() {
+            Some('ﾞ') => Some(VOICED_MARK),
+            Some('ﾟ') => Some(SEMI_VOICED_MARK),
+            _ => None,
+        };
+        match mark.and_then(|mark| compose(full, mark)) {
+            Some(composed) => {
+                chars.next();
+                out.push(composed);
+            }
+            None => out.push(full),
+        }
+    }
+
+    out
+}
+
+pub fn to_katakana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            // ぁ..ゖ and ゝゞ
+            '\u{3041}'..='\u{3096}' | '\u{309D}' | '\u{309E}' => {
+                char::from_u32(c as u32 + 0x60).unwrap()
+            }
+            c => c,
+        })
+        .collect()
+}
+
+pub fn to_hiragana(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '\u{30A1}'..='\u{30F6}' | '\u{30FD}' | '\u{30FE}' => {
+                char::from_u32(c as u32 - 0x60).unwrap()
+            }
+            c => c,
+        })
+        .collect()
+}
+
+/// Zero-width and formatting characters, and controls other than tabs and line breaks.
+fn is_invisible(c: char) -> bool {
+    match c {
+        '\t' | '\n' | '\r' => false,
+        // soft hyphen, zero-width spaces and joiners, direction marks and embeddings,
+        // word joiner and invisible operators, direction isolates, BOM
+        '\u{00AD}'
+        | '\u{180E}'
+        | '\u{200B}'..='\u{200F}'
+        | '\u{202A}'..='\u{202E}'
+        | '\u{2060}'..='\u{2064}'
+        | '\u{2066}'..='\u{2069}'
+        | '\u{FEFF}' => true,
+        c => c.is_control(),
+    }
+}
+
+pub fn strip_invisible(text: &str) -> String {
+    text.chars().filter(|c| !is_invisible(*c)).collect()
+}
+
+pub struct Normalize;
+
+impl BuiltinFunction for Normalize {
+    fn get_name(&self) -> &str {
+        "normalize"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (text, form): (String, Option<String>)| {
+            match form.as_deref().map(str::to_ascii_uppercase).as_deref() {
+                None | Some("NFC") => Ok(text.nfc().collect::<String>()),
+                Some("NFD") => Ok(text.nfd().collect()),
+                Some("NFKC") => Ok(text.nfkc().collect()),
+                Some("NFKD") => Ok(text.nfkd().collect()),
+                Some(form) => Err(mlua::Error::RuntimeError(format!(
+                    "unknown normalization form: {} (NFC, NFD, NFKC or NFKD)",
+                    form
+                ))),
+            }
+        })
+        .unwrap()
+    }
+}
+
+pub struct ToHalfwidth;
+
+impl BuiltinFunction for ToHalfwidth {
+    fn get_name(&self) -> &str {
+        "to_halfwidth"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (text, ranges): (String, Option<Table>)| {
+            Ok(to_halfwidth(&text, Ranges::from_lua(ranges)?))
+        })
+        .unwrap()
+    }
+}
+
+pub struct ToFullwidth;
+
+impl BuiltinFunction for ToFullwidth {
+    fn get_name(&self) -> &str {
+        "to_fullwidth"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (text, ranges): (String, Option<Table>)| {
+            Ok(to_fullwidth(&text, Ranges::from_lua(ranges)?))
+        })
+        .unwrap()
+    }
+}
+
+pub struct ToKatakana;
+
+impl BuiltinFunction for ToKatakana {
+    fn get_name(&self) -> &str {
+        "to_katakana"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| Ok(to_katakana(&text)))
+            .unwrap()
+    }
+}
+
+pub struct ToHiragana;
+
+impl BuiltinFunction for ToHiragana {
+    fn get_name(&self) -> &str {
+        "to_hiragana"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| Ok(to_hiragana(&text)))
+            .unwrap()
+    }
+}
+
+pub struct StripInvisible;
+
+impl BuiltinFunction for StripInvisible {
+    fn get_name(&self) -> &str {
+        "strip_invisible"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| Ok(strip_invisible(&text)))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tables() {
+        assert_eq!(
+            HALFWIDTH_KATAKANA.chars().count(),
+            FULLWIDTH_KATAKANA.chars().count()
+        );
+    }
+
+    #[test]
+    fn test_to_halfwidth() {
+        assert_eq!(
+            "ABC 123-ｶﾞｯｺｳ･ﾊﾟﾝ!",
+            to_halfwidth("ＡＢＣ　１２３－ガッコウ・パン！", Ranges::all())
+        );
+        assert_eq!(
+            "ABC　123－ガッコウ",
+            to_halfwidth(
+                "ＡＢＣ　１２３－ガッコウ",
+                Ranges {
+                    alnum: true,
+                    ..Default::default()
+                }
+            )
+        );
+        // no half-width form
+        assert_eq!("ひらがな ヰ", to_halfwidth("ひらがな　ヰ", Ranges::all()));
+    }
+
+    #[test]
+    fn test_to_fullwidth() {
+        assert_eq!(
+            "ＡＢＣ　１２３－ガッコウ・パン！ヴ",
+            to_fullwidth("ABC 123-ｶﾞｯｺｳ･ﾊﾟﾝ!ｳﾞ", Ranges::all())
+        );
+        assert_eq!(
+            "ABC 123-ガッコウア゛ア゜",
+            to_fullwidth(
+                "ABC 123-ｶﾞｯｺｳｱﾞｱﾟ",
+                Ranges {
+                    katakana: true,
+                    ..Default::default()
+                }
+            )
+        );
+    }
+
+    #[test]
+    fn test_kana() {
+        assert_eq!("カタカナ ヴヽ ｶﾅ", to_katakana("かたかな ゔゝ ｶﾅ"));
+        assert_eq!("ひらがな ゔゝ ヷ", to_hiragana("ヒラガナ ヴヽ ヷ"));
+    }
+
+    #[test]
+    fn test_strip_invisible() {
+        assert_eq!(
+            "山田\t太郎\r\n",
+            strip_invisible("\u{FEFF}山\u{200B}田\t太\u{00AD}郎\u{202E}\u{0007}\r\n")
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let lua = Lua::new();
+        Normalize {}.set_function(&lua).unwrap();
+        ToHalfwidth {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            assert(normalize("ｶﾞ①", "nfkc") == "ガ1")
+            assert(normalize("が", "NFD") == "か\u{3099}")
+            assert(normalize("か\u{3099}") == "が")
+            assert(not pcall(normalize, "a", "NFX"))
+            assert(to_halfwidth("Ａ　ア", { "alnum", "katakana" }) == "A　ｱ")
+            assert(not pcall(to_halfwidth, "a", { "kanji" }))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
@@ -0,0 +1,372 @@
+//! Regular expressions with the regex crate, as the `re` table
+//!
+//! Functions take a pattern or a regex from `re.compile`; patterns are compiled once and cached
+//! until the garbage collector frees the regex.
+//!
+//! # Example
+//! ```lua
+//! local pr = re.captures([[github\.com/(?<owner>[\w.-]+)/(?<repo>[\w.-]+)/pull/(?<number>\d+)]], qlp.text)
+//! print(pr.owner, pr.repo, pr.number)
+//!
+//! local words = re.compile([[\p{Han}+]])
+//! for _, w in ipairs(words:find_all(qlp.text)) do
+//!     print(w)
+//! end
+//!
+//! qlp.result = re.replace([[(?<y>\d{4})-(?<m>\d{2})-(?<d>\d{2})]], qlp.text, "$d/$m/$y")
+//! ```
+
+use mlua::{
+    AnyUserData, Lua, MetaMethod, MultiValue, Table, UserData, UserDataFields, UserDataMethods,
+    Value,
+};
+use regex::{Captures, Regex, RegexBuilder};
+
+const RE_CACHE: &str = "qlp.re_cache";
+
+pub struct LuaRegex(pub Regex);
+
+impl UserData for LuaRegex {
+    fn add_fields<F: UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("pattern", |_, this| Ok(this.0.as_str().to_string()));
+    }
+
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("match", |lua, this, (text, init): (String, Option<i64>)| {
+            match_(lua, &this.0, &text, init)
+        });
+        methods.add_method("find_all", |lua, this, text: String| {
+            find_all(lua, &this.0, &text)
+        });
+        methods.add_method("captures", |lua, this, text: String| {
+            captures(lua, &this.0, &text)
+        });
+        methods.add_method(
+            "replace",
+            |lua, this, (text, replacement, limit): (String, Value, Option<usize>)| {
+                replace(lua, &this.0, &text, replacement, limit)
+            },
+        );
+        methods.add_method(
+            "split",
+            |lua, this, (text, limit): (String, Option<usize>)| split(lua, &this.0, &text, limit),
+        );
+
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| {
+            Ok(this.0.as_str().to_string())
+        });
+    }
+}
+
+fn build_regex(pattern: &str, flags: &str) -> mlua::Result<Regex> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags.chars() {
+        match flag {
+            'i' => builder.case_insensitive(true),
+            'm' => builder.multi_line(true),
+            's' => builder.dot_matches_new_line(true),
+            'x' => builder.ignore_whitespace(true),
+            'U' => builder.swap_greed(true),
+            _ => {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "unknown regex flag: {} (i, m, s, x or U)",
+                    flag
+                )));
+            }
+        };
+    }
+
+    builder
+        .build()
+        .map_err(|e| mlua::Error::RuntimeError(format!("invalid regex: {}", e)))
+}
+
+/// Compiles `pattern`, or returns the regex compiled before for the same pattern and flags.
+fn compile(lua: &Lua, pattern: &str, flags: &str) -> mlua::Result<AnyUserData> {
+    let cache = match lua.named_registry_value::<Option<Table>>(RE_CACHE)? {
+        Some(cache) => cache,
+        None => {
+            // weak values, so that scripts building many patterns do not keep them all
+            let cache = lua.create_table()?;
+            let mt = lua.create_table()?;
+            mt.raw_set("__mode", "v")?;
+            cache.set_metatable(Some(mt))?;
+            lua.set_named_registry_value(RE_CACHE, &cache)?;
+            cache
+        }
+    };
+
+    let key = format!("{}/{}", flags, pattern);
+    if let Some(regex) = cache.get::<Option<AnyUserData>>(key.as_str())? {
+        return Ok(regex);
+    }
+
+    let regex = lua.create_userdata(LuaRegex(build_regex(pattern, flags)?))?;
+    cache.set(key, &regex)?;
+
+    Ok(regex)
+}
+
+fn get_regex(lua: &Lua, pattern: Value) -> mlua::Result<Regex> {
+    let regex = match pattern {
+        Value::String(s) => compile(lua, &s.to_str()?, "")?,
+        Value::UserData(regex) => regex,
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "expected a pattern or a regex from re.compile".to_string(),
+            ));
+        }
+    };
+
+    // Regex clones share the compiled program
+    Ok(regex.borrow::<LuaRegex>()?.0.clone())
+}
+
+/// `[0]` is the whole match, `[1]`.. the groups (nil when they did not take part),
+/// named groups are also set by name.
+fn captures_to_table(lua: &Lua, regex: &Regex, caps: &Captures) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    for (i, m) in caps.iter().enumerate() {
+        if let Some(m) = m {
+            table.raw_set(i, m.as_str())?;
+        }
+    }
+    for name in regex.capture_names().flatten() {
+        if let Some(m) = caps.name(name) {
+            table.raw_set(name, m.as_str())?;
+        }
+    }
+
+    Ok(table)
+}
+
+/// Like `string.match`: the groups of the first match, or the whole match when there are none.
+fn match_(lua: &Lua, regex: &Regex, text: &str, init: Option<i64>) -> mlua::Result<MultiValue> {
+    // 1-based, negative counts from the end as in string.find
+    let start = match init.unwrap_or(1) {
+        i if i > 0 => (i - 1) as usize,
+        0 => 0,
+        i => text.len().saturating_sub(i.unsigned_abs() as usize),
+    };
+    if start > text.len() || !text.is_char_boundary(start) {
+        return Ok(MultiValue::from_vec(vec![Value::Nil]));
+    }
+
+    let Some(caps) = regex.captures_at(text, start) else {
+        return Ok(MultiValue::from_vec(vec![Value::Nil]));
+    };
+
+    let values = if caps.len() == 1 {
+        vec![Value::String(lua.create_string(&caps[0])?)]
+    } else {
+        caps.iter()
+            .skip(1)
+            .map(|m| match m {
+                Some(m) => lua.create_string(m.as_str()).map(Value::String),
+                None => Ok(Value::Nil),
+            })
+            .collect::<mlua::Result<Vec<_>>>()?
+    };
+
+    Ok(MultiValue::from_vec(values))
+}
+
+fn find_all(lua: &Lua, regex: &Regex, text: &str) -> mlua::Result<Table> {
+    lua.create_sequence_from(regex.find_iter(text).map(|m| m.as_str()))
+}
+
+fn captures(lua: &Lua, regex: &Regex, text: &str) -> mlua::Result<Option<Table>> {
+    regex
+        .captures(text)
+        .map(|caps| captures_to_table(lua, regex, &caps))
+        .transpose()
+}
+
+/// Replaces the first `limit` matches (all by default) by a template using `$1`, `$name`
+/// or `${name}`, or by what a function returns for the captures; nil or false keeps the match.
+fn replace(
+    lua: &Lua,
+    regex: &Regex,
+    text: &str,
+    replacement: Value,
+    limit: Option<usize>,
+) -> mlua::Result<(String, usize)> {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    let mut count = 0;
+
+    for caps in regex.captures_iter(text) {
+        if limit.is_some_and(|limit| count >= limit) {
+            break;
+        }
+
+        let m = caps.get(0).unwrap();
+        out.push_str(&text[last..m.start()]);
+
+        match &replacement {
+            Value::String(template) => caps.expand(&template.to_str()?, &mut out),
+            Value::Function(f) => match f.call::<Value>(captures_to_table(lua, regex, &caps)?)? {
+                Value::Nil | Value::Boolean(false) => out.push_str(m.as_str()),
+                Value::String(s) => out.push_str(&s.to_str()?),
+                Value::Integer(n) => out.push_str(&n.to_string()),
+                Value::Number(n) => out.push_str(&n.to_string()),
+                _ => {
+                    return Err(mlua::Error::RuntimeError(
+                        "a replacement function must return a string".to_string(),
+                    ));
+                }
+            },
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "a replacement must be a string or a function".to_string(),
+                ));
+            }
+        }
+
+        last = m.end();
+        count += 1;
+    }
+    out.push_str(&text[last..]);
+
+    Ok((out, count))
+}
+
+/// Splits at the matches, into at most `limit` parts.
+fn split(lua: &Lua, regex: &Regex, text: &str, limit: Option<usize>) -> mlua::Result<Table> {
+    match limit {
+        Some(limit) => lua.create_sequence_from(regex.splitn(text, limit)),
+        None => lua.create_sequence_from(regex.split(text)),
+    }
+}
+
+pub struct Re;
+
+impl Re {
+    /// Sets the `re` table of functions.
+    pub fn set_table(&self, lua: &Lua) -> mlua::Result<()> {
+        let re = lua.create_table()?;
+
+        re.set(
+            "compile",
+            lua.create_function(|lua, (pattern, flags): (String, Option<String>)| {
+                compile(lua, &pattern, flags.as_deref().unwrap_or(""))
+            })?,
+        )?;
+        re.set(
+            "match",
+            lua.create_function(|lua, (pattern, text, init): (Value, String, Option<i64>)| {
+                match_(lua, &get_regex(lua, pattern)?, &text, init)
+            })?,
+        )?;
+        re.set(
+            "find_all",
+            lua.create_function(|lua, (pattern, text): (Value, String)| {
+                find_all(lua, &get_regex(lua, pattern)?, &text)
+            })?,
+        )?;
+        re.set(
+            "captures",
+            lua.create_function(|lua, (pattern, text): (Value, String)| {
+                captures(lua, &get_regex(lua, pattern)?, &text)
+            })?,
+        )?;
+        re.set(
+            "replace",
+            lua.create_function(
+                |lua, (pattern, text, replacement, limit): (Value, String, Value, Option<usize>)| {
+                    replace(lua, &get_regex(lua, pattern)?, &text, replacement, limit)
+                },
+            )?,
+        )?;
+        re.set(
+            "split",
+            lua.create_function(
+                |lua, (pattern, text, limit): (Value, String, Option<usize>)| {
+                    split(lua, &get_regex(lua, pattern)?, &text, limit)
+                },
+            )?,
+        )?;
+        re.set(
+            "escape",
+            lua.create_function(|_, text: String| Ok(regex::escape(&text)))?,
+        )?;
+
+        lua.globals().set("re", re)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_re() {
+        let lua = Lua::new();
+        Re {}.set_table(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local url = "https://github.com/octo-org/hello.world/pull/42/files"
+
+            -- alternation, Unicode classes and groups that Lua patterns lack
+            local owner, repo, number = re.match([[github\.com/([\w.-]+)/([\w.-]+)/(?:pull|issues)/(\d+)]], url)
+            assert(owner == "octo-org" and repo == "hello.world" and number == "42")
+            assert(re.match([[\p{Han}+]], "住所は東京都です") == "東京都")
+            assert(re.match("b+", "abba", 4) == nil and re.match("a", "abba", -1) == "a")
+            assert(re.match("x", "abc") == nil)
+            local a, b = re.match("(a)|(b)", "b")
+            assert(a == nil and b == "b")
+
+            local pr = re.captures([[/(?<owner>[\w.-]+)/(?<repo>[\w.-]+)/pull/(?<number>\d+)]], url)
+            assert(pr.owner == "octo-org" and pr.number == "42" and pr[3] == "42")
+            assert(pr[0] == "/octo-org/hello.world/pull/42")
+            assert(re.captures("x", url) == nil)
+
+            assert(table.concat(re.find_all([[\d+]], "a1b22c333"), ",") == "1,22,333")
+
+            local dates = [[(?<y>\d{4})-(?<m>\d{2})-(?<d>\d{2})]]
+            local s, n = re.replace(dates, "2024-01-31, 2024-02-29", "$d/$m/${y}")
+            assert(s == "31/01/2024, 29/02/2024" and n == 2)
+            assert(re.replace(dates, "2024-01-31, 2024-02-29", "$y", 1) == "2024, 2024-02-29")
+            assert(re.replace([[\d+]], "a1b22", function(c) return tostring(#c[0]) end) == "a1b2")
+            assert(re.replace([[\w+]], "keep drop", function(c) if c[0] == "drop" then return "x" end end) == "keep x")
+
+            assert(table.concat(re.split([[\s*[,;]\s*]], "a , b;c"), "|") == "a|b|c")
+            assert(table.concat(re.split(",", "a,b,c", 2), "|") == "a|b,c")
+
+            local rx = re.compile("HELLO", "i")
+            assert(rx == re.compile("HELLO", "i") and rx ~= re.compile("HELLO"))
+            assert(rx:match("say hello") == "hello" and rx.pattern == "HELLO" and tostring(rx) == "HELLO")
+            assert(re.match(rx, "Hello") == "Hello")
+            assert(rx:replace("hello hello", "bye", 1) == "bye hello")
+            assert(re.escape("a.b*") == [[a\.b\*]])
+
+            assert(not pcall(re.match, "(", "x"))
+            assert(not pcall(re.compile, "x", "q"))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_cache_is_collected() {
+        let lua = Lua::new();
+        Re {}.set_table(&lua).unwrap();
+
+        lua.load(
+            r#"
+            for i = 1, 100 do
+                re.match("x" .. i, "x1")
+            end
+            "#,
+        )
+        .exec()
+        .unwrap();
+        lua.gc_collect().unwrap();
+        lua.gc_collect().unwrap();
+
+        let cache = lua.named_registry_value::<Table>(RE_CACHE).unwrap();
+        assert_eq!(0, cache.pairs::<String, AnyUserData>().count());
+    }
+}
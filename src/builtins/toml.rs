@@ -0,0 +1,229 @@
+//! TOML conversion
+//!
+//! # Example
+//! ```lua
+//! local manifest = toml_to_table(qlp.text)
+//! print(manifest.package.name)
+//!
+//! qlp.result = toml_to_yaml(qlp.text)
+//! ```
+
+use mlua::{Function, Lua, Table, Value};
+use serde_json::{Map, Value as JsonValue};
+use toml::Value as TomlValue;
+
+use crate::utils::{json_to_lua, lua_to_json, parse_json};
+
+use super::{
+    builtin::BuiltinFunction,
+    yaml::{parse_yaml, to_yaml},
+};
+
+/// Parses TOML keeping the key order, date-times become strings.
+pub fn parse_toml(text: &str) -> mlua::Result<JsonValue> {
+    let table = toml::from_str::<toml::Table>(text)
+        .map_err(|e| mlua::Error::RuntimeError(format!("TOML parse error: {}", e)))?;
+
+    toml_to_json(TomlValue::Table(table))
+}
+
+pub fn to_toml(value: &JsonValue) -> mlua::Result<String> {
+    match json_to_toml(value)? {
+        TomlValue::Table(table) => {
+            toml::to_string(&table).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+        }
+        _ => Err(mlua::Error::RuntimeError(
+            "TOML must be a table at the top level".to_string(),
+        )),
+    }
+}
+
+fn toml_to_json(value: TomlValue) -> mlua::Result<JsonValue> {
+    Ok(match value {
+        TomlValue::String(s) => JsonValue::String(s),
+        TomlValue::Integer(i) => JsonValue::from(i),
+        TomlValue::Float(f) => {
+            JsonValue::Number(serde_json::Number::from_f64(f).ok_or_else(|| {
+                mlua::Error::RuntimeError(format!("{} cannot be represented in JSON", f))
+            })?)
+        }
+        TomlValue::Boolean(b) => JsonValue::Bool(b),
+        TomlValue::Datetime(d) => JsonValue::String(d.to_string()),
+        TomlValue::Array(arr) => JsonValue::Array(
+            arr.into_iter()
+                .map(toml_to_json)
+                .collect::<mlua::Result<Vec<_>>>()?,
+        ),
+        TomlValue::Table(table) => {
+            let mut obj = Map::new();
+            for (k, v) in table {
+                obj.insert(k, toml_to_json(v)?);
+            }
+            JsonValue::Object(obj)
+        }
+    })
+}
+
+fn json_to_toml(value: &JsonValue) -> mlua::Result<TomlValue> {
+    Ok(match value {
+        JsonValue::Null => {
+            return Err(mlua::Error::RuntimeError("TOML has no null".to_string()));
+        }
+        JsonValue::Bool(b) => TomlValue::Boolean(*b),
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) => TomlValue::Integer(i),
+            None => TomlValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+        },
+        JsonValue::String(s) => TomlValue::String(s.clone()),
+        JsonValue::Array(arr) => TomlValue::Array(
+            arr.iter()
+                .map(json_to_toml)
+                .collect::<mlua::Result<Vec<_>>>()?,
+        ),
+        JsonValue::Object(obj) => {
+            let mut table = toml::Table::new();
+            for (k, v) in obj {
+                table.insert(k.clone(), json_to_toml(v)?);
+            }
+            TomlValue::Table(table)
+        }
+    })
+}
+
+fn to_pretty_json(value: &JsonValue) -> mlua::Result<String> {
+    serde_json::to_string_pretty(value).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+}
+
+pub struct TomlToTable;
+
+impl BuiltinFunction for TomlToTable {
+    fn get_name(&self) -> &str {
+        "toml_to_table"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, text: String| json_to_lua(lua, &parse_toml(&text)?))
+            .unwrap()
+    }
+}
+
+pub struct TableToToml;
+
+impl BuiltinFunction for TableToToml {
+    fn get_name(&self) -> &str {
+        "table_to_toml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, table: Table| to_toml(&lua_to_json(lua, Value::Table(table))?))
+            .unwrap()
+    }
+}
+
+pub struct JsonToToml;
+
+impl BuiltinFunction for JsonToToml {
+    fn get_name(&self) -> &str {
+        "json_to_toml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, json: String| to_toml(&parse_json(&json)?))
+            .unwrap()
+    }
+}
+
+pub struct TomlToJson;
+
+impl BuiltinFunction for TomlToJson {
+    fn get_name(&self) -> &str {
+        "toml_to_json"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| to_pretty_json(&parse_toml(&text)?))
+            .unwrap()
+    }
+}
+
+pub struct YamlToToml;
+
+impl BuiltinFunction for YamlToToml {
+    fn get_name(&self) -> &str {
+        "yaml_to_toml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, yaml: String| to_toml(&parse_yaml(&yaml)?))
+            .unwrap()
+    }
+}
+
+pub struct TomlToYaml;
+
+impl BuiltinFunction for TomlToYaml {
+    fn get_name(&self) -> &str {
+        "toml_to_yaml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, text: String| to_yaml(&parse_toml(&text)?))
+            .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toml_to_json() {
+        let json = parse_toml(
+            r#"
+[package]
+name = "qlp"
+version = "0.1.0"
+released = 2024-01-31T09:30:00Z
+
+[[bin]]
+name = "qlp"
+"#,
+        )
+        .unwrap();
+
+        // key order is kept
+        assert_eq!(
+            r#"{"package":{"name":"qlp","version":"0.1.0","released":"2024-01-31T09:30:00Z"},"bin":[{"name":"qlp"}]}"#,
+            serde_json::to_string(&json).unwrap()
+        );
+        assert!(parse_toml("a = ").is_err());
+    }
+
+    #[test]
+    fn test_json_to_toml() {
+        assert_eq!(
+            "name = \"qlp\"\n\n[dependencies]\nmlua = \"0.11\"\n",
+            to_toml(&parse_json(r#"{"name":"qlp","dependencies":{"mlua":"0.11"}}"#).unwrap())
+                .unwrap()
+        );
+        assert!(to_toml(&parse_json("[1]").unwrap()).is_err());
+        assert!(to_toml(&parse_json(r#"{"a":null}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_toml_to_table() {
+        let lua = Lua::new();
+        TomlToTable {}.set_function(&lua).unwrap();
+        TableToToml {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local t = toml_to_table('title = "x"\n[owner]\nname = "y"\nids = [1, 2]\n')
+            assert(t.title == "x" and t.owner.name == "y" and t.owner.ids[2] == 2)
+            assert(table_to_toml({ b = 1, a = "x" }) == 'a = "x"\nb = 1\n')
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
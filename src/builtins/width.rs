@@ -0,0 +1,367 @@
+//! Display width of text and fixed-width layouts
+//!
+//! Widths follow East Asian Width, or count Shift_JIS bytes with `{ sjis = true }`, where
+//! characters missing in Shift_JIS are an error.
+//!
+//! # Example
+//! ```lua
+//! -- fixed-length records from a copied table
+//! local widths = { 10, { width = 6, align = "right" }, 20 }
+//! qlp.result = s(format_fixed_columns(qlp.tables[1], widths, { sjis = true }))
+//!
+//! print(pad("山田", 10, "right") .. "|" .. truncate("とても長い名前", 8, { ellipsis = "…" }))
+//! ```
+
+use encoding_rs::{EncoderResult, SHIFT_JIS};
+use mlua::{Function, Lua, Table, Value};
+use unicode_width::UnicodeWidthChar;
+
+use crate::utils::{lua_table_rows, lua_value_to_text};
+
+use super::builtin::BuiltinFunction;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    /// terminal columns, `cjk` makes ambiguous characters such as `○` wide
+    Display {
+        cjk: bool,
+    },
+    ShiftJis,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Align {
+    Left,
+    Right,
+    Center,
+}
+
+impl Measure {
+    fn from_lua(opts: &Option<Table>) -> mlua::Result<Self> {
+        let Some(opts) = opts else {
+            return Ok(Self::Display { cjk: false });
+        };
+
+        if opts.get::<Option<bool>>("sjis")?.unwrap_or(false) {
+            Ok(Self::ShiftJis)
+        } else {
+            Ok(Self::Display {
+                cjk: opts.get::<Option<bool>>("cjk")?.unwrap_or(false),
+            })
+        }
+    }
+
+    pub fn char_width(&self, c: char) -> usize {
+        match self {
+            Self::Display { cjk: false } => c.width().unwrap_or(0),
+            Self::Display { cjk: true } => c.width_cjk().unwrap_or(0),
+            // text is checked before it is measured
+            Self::ShiftJis => sjis_len(c).unwrap_or(2),
+        }
+    }
+
+    pub fn width(&self, text: &str) -> usize {
+        text.chars().map(|c| self.char_width(c)).sum()
+    }
+
+    /// Rejects text with characters that have no width in this measure.
+    pub fn check(&self, text: &str) -> Result<(), String> {
+        if *self == Self::ShiftJis
+            && let Some(c) = text.chars().find(|c| sjis_len(*c).is_none())
+        {
+            return Err(format!(
+                "{:?} (U+{:04X}) cannot be encoded in Shift_JIS",
+                c, c as u32
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of bytes of `c` in Shift_JIS, `None` if it has no mapping.
+fn sjis_len(c: char) -> Option<usize> {
+    let mut buf = [0u8; 4];
+    let (result, _, written) = SHIFT_JIS
+        .new_encoder()
+        .encode_from_utf8_without_replacement(c.encode_utf8(&mut [0u8; 4]), &mut buf, true);
+
+    match result {
+        EncoderResult::InputEmpty => Some(written),
+        _ => None,
+    }
+}
+
+/// Checks `text` for `measure`, as an error of Lua.
+fn check(text: &str, measure: Measure) -> mlua::Result<()> {
+    measure.check(text).map_err(mlua::Error::RuntimeError)
+}
+
+impl Align {
+    fn from_lua(align: Option<String>) -> mlua::Result<Self> {
+        match align.as_deref() {
+            None | Some("left") => Ok(Self::Left),
+            Some("right") => Ok(Self::Right),
+            Some("center") => Ok(Self::Center),
+            Some(s) => Err(mlua::Error::RuntimeError(format!(
+                "align must be left, right or center: {}",
+                s
+            ))),
+        }
+    }
+}
+
+/// `width` columns of `fill`, spaces where a wide fill does not fit.
+fn filler(width: usize, fill: &str, measure: Measure) -> String {
+    let fill_width = measure.width(fill);
+    if fill_width == 0 {
+        return " ".repeat(width);
+    }
+
+    let mut s = fill.repeat(width / fill_width);
+    s.push_str(&" ".repeat(width % fill_width));
+    s
+}
+
+/// Pads `text` to `width`, text already as wide is returned as it is.
+pub fn pad(text: &str, width: usize, align: Align, fill: &str, measure: Measure) -> String {
+    let rest = width.saturating_sub(measure.width(text));
+    let (left, right) = match align {
+        Align::Left => (0, rest),
+        Align::Right => (rest, 0),
+        Align::Center => (rest / 2, rest - rest / 2),
+    };
+
+    format!(
+        "{}{}{}",
+        filler(left, fill, measure),
+        text,
+        filler(right, fill, measure)
+    )
+}
+
+/// Cuts `text` to at most `width`, ending with `ellipsis` when cut. Wide characters are not split.
+pub fn truncate(text: &str, width: usize, ellipsis: &str, measure: Measure) -> String {
+    if measure.width(text) <= width {
+        return text.to_string();
+    }
+
+    let ellipsis_width = measure.width(ellipsis);
+    let (limit, ellipsis) = if ellipsis_width <= width {
+        (width - ellipsis_width, ellipsis)
+    } else {
+        (width, "")
+    };
+
+    let mut out = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = measure.char_width(c);
+        if used + w > limit {
+            break;
+        }
+        used += w;
+        out.push(c);
+    }
+    out.push_str(ellipsis);
+
+    out
+}
+
+/// Cuts and pads `text` to exactly `width`.
+pub fn fit(text: &str, width: usize, align: Align, measure: Measure) -> String {
+    pad(
+        &truncate(text, width, "", measure),
+        width,
+        align,
+        " ",
+        measure,
+    )
+}
+
+fn get_string(opts: &Option<Table>, key: &str, default: &str) -> mlua::Result<String> {
+    match opts {
+        Some(opts) => Ok(opts
+            .get::<Option<String>>(key)?
+            .unwrap_or_else(|| default.to_string())),
+        None => Ok(default.to_string()),
+    }
+}
+
+/// A column is a width or `{ width = n, align = "right" }`.
+fn get_columns(widths: &Table) -> mlua::Result<Vec<(usize, Align)>> {
+    widths
+        .sequence_values::<Value>()
+        .map(|column| match column? {
+            Value::Integer(n) if n >= 0 => Ok((n as usize, Align::Left)),
+            Value::Table(t) => Ok((t.get::<usize>("width")?, Align::from_lua(t.get("align")?)?)),
+            _ => Err(mlua::Error::RuntimeError(
+                "widths must be numbers or { width, align } tables".to_string(),
+            )),
+        })
+        .collect()
+}
+
+fn format_fixed_columns(rows: &Table, widths: &Table, opts: Option<Table>) -> mlua::Result<String> {
+    let measure = Measure::from_lua(&opts)?;
+    let separator = get_string(&opts, "separator", "")?;
+    let terminator = get_string(&opts, "terminator", "\n")?;
+    let columns = get_columns(widths)?;
+
+    let rows = lua_table_rows(rows)?;
+
+    let mut out = String::new();
+    for (r, row) in rows.sequence_values::<Table>().enumerate() {
+        let row = row?;
+        let mut fields = Vec::with_capacity(columns.len());
+        for (i, (width, align)) in columns.iter().enumerate() {
+            // a line break would split the record
+            let cell = lua_value_to_text(row.get::<Value>(i + 1)?)?
+                .replace("\r\n", " ")
+                .replace(['\r', '\n'], " ");
+            measure.check(&cell).map_err(|e| {
+                mlua::Error::RuntimeError(format!("row {}, column {}: {}", r + 1, i + 1, e))
+            })?;
+            fields.push(fit(&cell, *width, *align, measure));
+        }
+
+        out.push_str(&fields.join(&separator));
+        out.push_str(&terminator);
+    }
+
+    Ok(out)
+}
+
+pub struct DisplayWidth;
+
+impl BuiltinFunction for DisplayWidth {
+    fn get_name(&self) -> &str {
+        "display_width"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (text, opts): (String, Option<Table>)| {
+            let measure = Measure::from_lua(&opts)?;
+            check(&text, measure)?;
+            Ok(measure.width(&text))
+        })
+        .unwrap()
+    }
+}
+
+pub struct Pad;
+
+impl BuiltinFunction for Pad {
+    fn get_name(&self) -> &str {
+        "pad"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(
+            |_, (text, width, align, opts): (String, usize, Option<String>, Option<Table>)| {
+                let fill = get_string(&opts, "fill", " ")?;
+                let measure = Measure::from_lua(&opts)?;
+                check(&text, measure)?;
+                check(&fill, measure)?;
+                Ok(pad(&text, width, Align::from_lua(align)?, &fill, measure))
+            },
+        )
+        .unwrap()
+    }
+}
+
+pub struct Truncate;
+
+impl BuiltinFunction for Truncate {
+    fn get_name(&self) -> &str {
+        "truncate"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (text, width, opts): (String, usize, Option<Table>)| {
+            let ellipsis = get_string(&opts, "ellipsis", "")?;
+            let measure = Measure::from_lua(&opts)?;
+            check(&text, measure)?;
+            check(&ellipsis, measure)?;
+            Ok(truncate(&text, width, &ellipsis, measure))
+        })
+        .unwrap()
+    }
+}
+
+pub struct FormatFixedColumns;
+
+impl BuiltinFunction for FormatFixedColumns {
+    fn get_name(&self) -> &str {
+        "format_fixed_columns"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (rows, widths, opts): (Table, Table, Option<Table>)| {
+            format_fixed_columns(&rows, &widths, opts)
+        })
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DISPLAY: Measure = Measure::Display { cjk: false };
+
+    #[test]
+    fn test_width() {
+        assert_eq!(9, DISPLAY.width("abcｱｲ日本"));
+        assert_eq!(9, Measure::ShiftJis.width("abcｱｲ日本"));
+        assert_eq!(1, DISPLAY.width("○"));
+        assert_eq!(2, Measure::Display { cjk: true }.width("○"));
+        assert_eq!(4, Measure::ShiftJis.width("¥a×"));
+        assert_eq!(Ok(()), Measure::ShiftJis.check("①髙"));
+        assert!(Measure::ShiftJis.check("𠮷").is_err());
+        assert!(Measure::ShiftJis.check("😀").is_err());
+        assert_eq!(Ok(()), DISPLAY.check("𠮷"));
+    }
+
+    #[test]
+    fn test_pad() {
+        assert_eq!("日本  ", pad("日本", 6, Align::Left, " ", DISPLAY));
+        assert_eq!("  日本", pad("日本", 6, Align::Right, " ", DISPLAY));
+        assert_eq!(" 日本  ", pad("日本", 7, Align::Center, " ", DISPLAY));
+        assert_eq!("0042", pad("42", 4, Align::Right, "0", DISPLAY));
+        assert_eq!("ab　　 ", pad("ab", 7, Align::Left, "　", DISPLAY));
+        assert_eq!("日本語", pad("日本語", 4, Align::Left, " ", DISPLAY));
+    }
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!("日本", truncate("日本語", 5, "", DISPLAY));
+        assert_eq!("日…", truncate("日本語", 4, "…", DISPLAY));
+        assert_eq!("日本語", truncate("日本語", 6, "…", DISPLAY));
+        assert_eq!("ｱｲｳ日", truncate("ｱｲｳ日本", 5, "", Measure::ShiftJis));
+        assert_eq!("日本 ", fit("日本語", 5, Align::Left, DISPLAY));
+    }
+
+    #[test]
+    fn test_format_fixed_columns() {
+        let lua = Lua::new();
+        FormatFixedColumns {}.set_function(&lua).unwrap();
+        Pad {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local rows = { { "0001", "山田太郎", 1200 }, { "0002", "ﾔﾏﾀﾞﾊﾅｺ", { text = "35" } } }
+            assert(format_fixed_columns(rows, { 4, 6, { width = 5, align = "right" } }, { separator = "|" })
+                == "0001|山田太| 1200\n0002|ﾔﾏﾀﾞﾊﾅ|   35\n")
+            assert(format_fixed_columns({ rows = rows }, { 4, 10 }, { sjis = true, terminator = "\r\n" })
+                == "0001山田太郎  \r\n0002ﾔﾏﾀﾞﾊﾅｺ   \r\n")
+            assert(format_fixed_columns({ { "a\r\nb", "c\nd" } }, { 4, 3 }) == "a b c d\n")
+            assert(not pcall(format_fixed_columns, { { "𠮷" } }, { 4 }, { sjis = true }))
+            assert(pad("7", 3, "right", { fill = "0" }) == "007")
+            assert(not pcall(pad, "7", 3, "middle"))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
@@ -0,0 +1,284 @@
+//! XML conversion and queries
+//!
+//! # Example
+//! ```lua
+//! -- versions of the dependencies of a copied pom.xml
+//! for _, dependency in ipairs(xml_query(qlp.text, "//dependency")) do
+//!     print(xml_query(dependency, "artifactId")[1].text, xml_query(dependency, "version/text()")[1])
+//! end
+//!
+//! local envelope = xml_to_table(qlp.text)
+//! print(envelope.name, envelope.namespace, envelope.children[1].local_name)
+//!
+//! qlp.result = prettier_xml(qlp.text)
+//! ```
+
+use mlua::{Function, Lua, Table, Value};
+
+use crate::xml::{
+    XmlElement, XmlMatch, XmlNode, minify_xml, parse_xml, prettier_xml, query_xml, write_xml,
+};
+
+use super::builtin::BuiltinFunction;
+
+pub struct XmlToTable;
+
+impl BuiltinFunction for XmlToTable {
+    fn get_name(&self) -> &str {
+        "xml_to_table"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, xml: String| {
+            let document = parse_xml(&xml).map_err(mlua::Error::RuntimeError)?;
+            let root = document
+                .root()
+                .ok_or_else(|| mlua::Error::RuntimeError("no root element".to_string()))?;
+            element_to_lua(lua, root)
+        })
+        .unwrap()
+    }
+}
+
+pub struct TableToXml;
+
+impl BuiltinFunction for TableToXml {
+    fn get_name(&self) -> &str {
+        "table_to_xml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (table, opts): (Table, Option<Table>)| table_to_xml(&table, opts))
+            .unwrap()
+    }
+}
+
+pub struct PrettierXml;
+
+impl BuiltinFunction for PrettierXml {
+    fn get_name(&self) -> &str {
+        "prettier_xml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, (xml, indent): (String, Value)| {
+            let indent = get_indent(indent)?.unwrap_or_else(|| "  ".to_string());
+            prettier_xml(&xml, &indent).map_err(mlua::Error::RuntimeError)
+        })
+        .unwrap()
+    }
+}
+
+pub struct MinifyXml;
+
+impl BuiltinFunction for MinifyXml {
+    fn get_name(&self) -> &str {
+        "minify_xml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, xml: String| minify_xml(&xml).map_err(mlua::Error::RuntimeError))
+            .unwrap()
+    }
+}
+
+pub struct XmlQuery;
+
+impl BuiltinFunction for XmlQuery {
+    fn get_name(&self) -> &str {
+        "xml_query"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, (source, path): (Value, String)| {
+            // a table from xml_to_table is the context of relative paths
+            let context = match source {
+                Value::String(xml) => {
+                    parse_xml(&xml.to_str()?).map_err(mlua::Error::RuntimeError)?
+                }
+                Value::Table(table) => lua_to_element(&table)?,
+                _ => {
+                    return Err(mlua::Error::RuntimeError(
+                        "xml_query expects an XML string or an element table".to_string(),
+                    ));
+                }
+            };
+
+            let matches = query_xml(&context, &path).map_err(mlua::Error::RuntimeError)?;
+
+            let result = lua.create_table()?;
+            for m in matches {
+                match m {
+                    XmlMatch::Element(e) => result.push(element_to_lua(lua, e)?)?,
+                    XmlMatch::Text(text) => result.push(text)?,
+                }
+            }
+            Ok(result)
+        })
+        .unwrap()
+    }
+}
+
+/// Reads an indent given as a number of spaces or a string.
+fn get_indent(indent: Value) -> mlua::Result<Option<String>> {
+    match indent {
+        Value::Nil => Ok(None),
+        Value::Integer(n) if n >= 0 => Ok(Some(" ".repeat(n as usize))),
+        Value::String(s) => Ok(Some(s.to_str()?.to_string())),
+        _ => Err(mlua::Error::RuntimeError(
+            "indent must be a number of spaces or a string".to_string(),
+        )),
+    }
+}
+
+/// `{ name, local_name, prefix, namespace, attrs, children, text }`, children are
+/// element tables and strings.
+fn element_to_lua(lua: &Lua, element: &XmlElement) -> mlua::Result<Table> {
+    let table = lua.create_table()?;
+    table.set("name", element.name())?;
+    table.set("local_name", element.local_name.as_str())?;
+    table.set("prefix", element.prefix.as_deref())?;
+    table.set("namespace", element.namespace.as_deref())?;
+
+    let attrs = lua.create_table()?;
+    for (k, v) in &element.attrs {
+        attrs.set(k.as_str(), v.as_str())?;
+    }
+    table.set("attrs", attrs)?;
+
+    let children = lua.create_table()?;
+    for child in &element.children {
+        match child {
+            XmlNode::Element(e) => children.push(element_to_lua(lua, e)?)?,
+            // whitespace between elements is only indentation
+            XmlNode::Text(t) if t.trim().is_empty() && element.elements().next().is_some() => {}
+            XmlNode::Text(t) => children.push(t.as_str())?,
+            _ => {}
+        }
+    }
+    table.set("children", children)?;
+    table.set("text", element.text())?;
+
+    Ok(table)
+}
+
+fn lua_to_element(table: &Table) -> mlua::Result<XmlElement> {
+    let name = table
+        .get::<Option<String>>("name")?
+        .ok_or_else(|| mlua::Error::RuntimeError("an element table needs a name".to_string()))?;
+    let mut element = XmlElement::new(&name);
+    element.namespace = table.get("namespace")?;
+
+    if let Some(attrs) = table.get::<Option<Table>>("attrs")? {
+        for pair in attrs.pairs::<String, Value>() {
+            let (k, v) = pair?;
+            element.attrs.push((k, lua_value_to_text(v)?));
+        }
+        // declarations first, then by name as Lua tables have no order
+        element
+            .attrs
+            .sort_by_key(|(k, _)| (!(k == "xmlns" || k.starts_with("xmlns:")), k.clone()));
+    }
+
+    match table.get::<Option<Table>>("children")? {
+        Some(children) => {
+            for child in children.sequence_values::<Value>() {
+                element.children.push(match child? {
+                    Value::Table(t) => XmlNode::Element(lua_to_element(&t)?),
+                    value => XmlNode::Text(lua_value_to_text(value)?),
+                });
+            }
+        }
+        None => {
+            if let Some(text) = table.get::<Option<Value>>("text")? {
+                element
+                    .children
+                    .push(XmlNode::Text(lua_value_to_text(text)?));
+            }
+        }
+    }
+
+    Ok(element)
+}
+
+fn lua_value_to_text(value: Value) -> mlua::Result<String> {
+    match value {
+        Value::String(s) => Ok(s.to_str()?.to_string()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(mlua::Error::RuntimeError(
+            "attributes and text must be strings, numbers or booleans".to_string(),
+        )),
+    }
+}
+
+fn table_to_xml(table: &Table, opts: Option<Table>) -> mlua::Result<String> {
+    let (indent, declaration) = match &opts {
+        Some(opts) => (
+            get_indent(opts.get("indent")?)?,
+            opts.get::<Option<bool>>("declaration")?.unwrap_or(false),
+        ),
+        None => (None, false),
+    };
+
+    let mut nodes = Vec::new();
+    if declaration {
+        nodes.push(XmlNode::ProcessingInstruction {
+            target: "xml".to_string(),
+            data: r#"version="1.0" encoding="UTF-8""#.to_string(),
+        });
+    }
+    nodes.push(XmlNode::Element(lua_to_element(table)?));
+
+    Ok(write_xml(&nodes, indent.as_deref(), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_xml() {
+        let lua = Lua::new();
+        XmlToTable {}.set_function(&lua).unwrap();
+        TableToXml {}.set_function(&lua).unwrap();
+        PrettierXml {}.set_function(&lua).unwrap();
+        MinifyXml {}.set_function(&lua).unwrap();
+        XmlQuery {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local soap = [[
+            <soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope">
+              <soap:Body><m:GetPrice xmlns:m="https://example.com/prices"><m:Item id="7">Apples</m:Item></m:GetPrice></soap:Body>
+            </soap:Envelope>]]
+
+            local envelope = xml_to_table(soap)
+            assert(envelope.name == "soap:Envelope" and envelope.local_name == "Envelope")
+            assert(envelope.prefix == "soap" and envelope.namespace == "http://www.w3.org/2003/05/soap-envelope")
+            assert(#envelope.children == 1)
+            local price = envelope.children[1].children[1]
+            assert(price.namespace == "https://example.com/prices" and price.attrs["xmlns:m"] == "https://example.com/prices")
+            assert(price.children[1].attrs.id == "7" and price.children[1].children[1] == "Apples")
+
+            assert(xml_query(soap, "//m:Item/@id")[1] == "7")
+            assert(xml_query(soap, "/Envelope/Body/GetPrice/Item")[1].text == "Apples")
+            assert(xml_query(envelope, "soap:Body/*")[1].local_name == "GetPrice")
+            assert(#xml_query(soap, "//Item[@id='8']") == 0)
+            assert(not pcall(xml_query, soap, "//Item["))
+
+            assert(table_to_xml(envelope) == minify_xml(soap))
+            assert(table_to_xml({ name = "a", attrs = { y = 1, x = "<\"&" }, children = { "t", { name = "b", text = 2 } } })
+                == '<a x="&lt;&quot;&amp;" y="1">t<b>2</b></a>')
+            assert(table_to_xml({ name = "a", children = { { name = "b" } } }, { indent = 2, declaration = true })
+                == '<?xml version="1.0" encoding="UTF-8"?>\n<a>\n  <b/>\n</a>\n')
+            assert(prettier_xml("<a><b>x</b></a>", "\t") == "<a>\n\t<b>x</b>\n</a>\n")
+            assert(not pcall(xml_to_table, ""))
+            assert(not pcall(prettier_xml, "<a><b></a>"))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
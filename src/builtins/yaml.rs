@@ -0,0 +1,241 @@
+//! YAML conversion
+//!
+//! # Example
+//! ```lua
+//! local manifest = yaml_to_table(qlp.text)
+//! print(manifest.metadata.name)
+//!
+//! -- manifests separated by ---
+//! for _, document in ipairs(yaml_documents(qlp.text)) do
+//!     print(document.kind)
+//! end
+//!
+//! qlp.result = yaml_to_json(qlp.text)
+//! ```
+
+use mlua::{Function, Lua, Table, Value};
+use serde::Deserialize;
+use serde_json::{Map, Value as JsonValue};
+use serde_norway::Value as YamlValue;
+
+use crate::utils::{json_to_lua, lua_to_json, parse_json};
+
+use super::builtin::BuiltinFunction;
+
+/// Parses YAML of one document keeping the key order, an empty text is null.
+pub fn parse_yaml(yaml: &str) -> mlua::Result<JsonValue> {
+    let mut documents = parse_yaml_documents(yaml)?;
+    match documents.len() {
+        0 => Ok(JsonValue::Null),
+        1 => Ok(documents.pop().unwrap()),
+        n => Err(mlua::Error::RuntimeError(format!(
+            "YAML has {} documents, read them with yaml_documents",
+            n
+        ))),
+    }
+}
+
+/// Parses each document of a stream separated by `---`.
+pub fn parse_yaml_documents(yaml: &str) -> mlua::Result<Vec<JsonValue>> {
+    serde_norway::Deserializer::from_str(yaml)
+        .map(|document| {
+            let value = YamlValue::deserialize(document)
+                .map_err(|e| mlua::Error::RuntimeError(format!("YAML parse error: {}", e)))?;
+            yaml_to_json(value)
+        })
+        .collect()
+}
+
+pub fn to_yaml(value: &JsonValue) -> mlua::Result<String> {
+    serde_norway::to_string(value).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+}
+
+fn yaml_to_json(value: YamlValue) -> mlua::Result<JsonValue> {
+    Ok(match value {
+        YamlValue::Null => JsonValue::Null,
+        YamlValue::Bool(b) => JsonValue::Bool(b),
+        YamlValue::Number(n) => {
+            if let Some(i) = n.as_i64() {
+                JsonValue::from(i)
+            } else if let Some(u) = n.as_u64() {
+                JsonValue::from(u)
+            } else {
+                let f = n.as_f64().unwrap_or(f64::NAN);
+                JsonValue::Number(serde_json::Number::from_f64(f).ok_or_else(|| {
+                    mlua::Error::RuntimeError(format!("{} cannot be represented in JSON", n))
+                })?)
+            }
+        }
+        YamlValue::String(s) => JsonValue::String(s),
+        YamlValue::Sequence(seq) => JsonValue::Array(
+            seq.into_iter()
+                .map(yaml_to_json)
+                .collect::<mlua::Result<Vec<_>>>()?,
+        ),
+        YamlValue::Mapping(mapping) => {
+            let mut obj = Map::new();
+            for (k, v) in mapping {
+                obj.insert(yaml_key_to_string(k)?, yaml_to_json(v)?);
+            }
+            JsonValue::Object(obj)
+        }
+        // custom tags such as `!Ref` keep their value only
+        YamlValue::Tagged(tagged) => yaml_to_json(tagged.value)?,
+    })
+}
+
+/// Scalar keys such as `1:` or `true:` become strings, as JSON and Lua field names.
+fn yaml_key_to_string(key: YamlValue) -> mlua::Result<String> {
+    match key {
+        YamlValue::String(s) => Ok(s),
+        YamlValue::Number(n) => Ok(n.to_string()),
+        YamlValue::Bool(b) => Ok(b.to_string()),
+        YamlValue::Null => Ok("null".to_string()),
+        YamlValue::Tagged(tagged) => yaml_key_to_string(tagged.value),
+        _ => Err(mlua::Error::RuntimeError(
+            "YAML keys must be scalars".to_string(),
+        )),
+    }
+}
+
+pub struct YamlToTable;
+
+impl BuiltinFunction for YamlToTable {
+    fn get_name(&self) -> &str {
+        "yaml_to_table"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, yaml: String| json_to_lua(lua, &parse_yaml(&yaml)?))
+            .unwrap()
+    }
+}
+
+pub struct YamlDocuments;
+
+impl BuiltinFunction for YamlDocuments {
+    fn get_name(&self) -> &str {
+        "yaml_documents"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, yaml: String| {
+            let documents = lua.create_table()?;
+            for document in parse_yaml_documents(&yaml)? {
+                documents.push(json_to_lua(lua, &document)?)?;
+            }
+            Ok(documents)
+        })
+        .unwrap()
+    }
+}
+
+pub struct TableToYaml;
+
+impl BuiltinFunction for TableToYaml {
+    fn get_name(&self) -> &str {
+        "table_to_yaml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|lua, table: Table| to_yaml(&lua_to_json(lua, Value::Table(table))?))
+            .unwrap()
+    }
+}
+
+pub struct JsonToYaml;
+
+impl BuiltinFunction for JsonToYaml {
+    fn get_name(&self) -> &str {
+        "json_to_yaml"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, json: String| to_yaml(&parse_json(&json)?))
+            .unwrap()
+    }
+}
+
+pub struct YamlToJson;
+
+impl BuiltinFunction for YamlToJson {
+    fn get_name(&self) -> &str {
+        "yaml_to_json"
+    }
+
+    fn get_function(&self, lua: &Lua) -> Function {
+        lua.create_function(|_, yaml: String| {
+            serde_json::to_string_pretty(&parse_yaml(&yaml)?)
+                .map_err(|e| mlua::Error::RuntimeError(e.to_string()))
+        })
+        .unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_yaml_to_json() {
+        let json = parse_yaml(
+            r#"
+apiVersion: v1
+kind: Service
+metadata:
+  name: web
+  labels: { app: web }
+spec:
+  ports:
+    - port: 80
+      targetPort: 8080
+  1: one
+"#,
+        )
+        .unwrap();
+
+        // key order is kept
+        assert_eq!(
+            r#"{"apiVersion":"v1","kind":"Service","metadata":{"name":"web","labels":{"app":"web"}},"spec":{"ports":[{"port":80,"targetPort":8080}],"1":"one"}}"#,
+            serde_json::to_string(&json).unwrap()
+        );
+        assert_eq!(
+            "apiVersion: v1\nkind: Service\n",
+            to_yaml(&parse_json(r#"{"apiVersion":"v1","kind":"Service"}"#).unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_yaml_documents() {
+        let documents = parse_yaml_documents("a: 1\n---\nb: !Ref x\n").unwrap();
+        assert_eq!(
+            r#"[{"a":1},{"b":"x"}]"#,
+            serde_json::to_string(&documents).unwrap()
+        );
+        // a list is one document
+        assert_eq!(1, parse_yaml_documents("- a\n- b\n").unwrap().len());
+
+        assert!(parse_yaml("a: 1\n---\nb: 2\n").is_err());
+        assert!(parse_yaml("a: [").is_err());
+    }
+
+    #[test]
+    fn test_yaml_to_table() {
+        let lua = Lua::new();
+        YamlToTable {}.set_function(&lua).unwrap();
+        YamlDocuments {}.set_function(&lua).unwrap();
+        TableToYaml {}.set_function(&lua).unwrap();
+
+        lua.load(
+            r#"
+            local t = yaml_to_table("name: web\nports: [80, 443]\nenabled: true\n")
+            assert(t.name == "web" and t.ports[2] == 443 and t.enabled == true)
+            local documents = yaml_documents("kind: A\n---\nkind: B\n")
+            assert(#documents == 2 and documents[2].kind == "B")
+            assert(table_to_yaml({ b = 1, a = { "x", "y" } }) == "a:\n- x\n- y\nb: 1\n")
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
@@ -0,0 +1,374 @@
+//! CF_HTML ("HTML Format") clipboard payload
+//!
+//! ```text
+//! Version:0.9
+//! StartHTML:0000000105
+//! EndHTML:0000000180
+//! StartFragment:0000000137
+//! EndFragment:0000000148
+//! <html><body><!--StartFragment--><b>text</b><!--EndFragment--></body></html>
+//! ```
+//!
+//! All offsets are byte offsets from the beginning of the payload.
+
+use std::ops::Range;
+
+use crate::error::Error;
+
+const DEFAULT_VERSION: &str = "0.9";
+const START_FRAGMENT: &str = "<!--StartFragment-->";
+const END_FRAGMENT: &str = "<!--EndFragment-->";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CfHtml {
+    version: String,
+    source_url: Option<String>,
+    /// StartHTML..EndHTML
+    html: String,
+    /// StartFragment..EndFragment, relative to `html`
+    fragment: Range<usize>,
+    /// StartSelection..EndSelection, relative to `html`
+    selection: Option<Range<usize>>,
+}
+
+#[derive(Debug, Default)]
+struct Header {
+    version: Option<String>,
+    start_html: Option<i64>,
+    end_html: Option<i64>,
+    start_fragment: Option<i64>,
+    end_fragment: Option<i64>,
+    start_selection: Option<i64>,
+    end_selection: Option<i64>,
+    source_url: Option<String>,
+    /// byte length of the header lines
+    len: usize,
+}
+
+impl CfHtml {
+    /// Creates a payload for `html`.
+    ///
+    /// The fragment is delimited by `<!--StartFragment-->` / `<!--EndFragment-->` if `html`
+    /// contains them, otherwise the whole `html` is the fragment.
+    pub fn new(html: &str) -> Self {
+        let fragment = html
+            .find(START_FRAGMENT)
+            .map(|start| start + START_FRAGMENT.len())
+            .and_then(|start| {
+                html[start..]
+                    .find(END_FRAGMENT)
+                    .map(|end| start..(start + end))
+            })
+            .unwrap_or(0..html.len());
+
+        Self {
+            version: DEFAULT_VERSION.to_string(),
+            source_url: None,
+            html: html.to_string(),
+            fragment,
+            selection: None,
+        }
+    }
+
+    /// Parses `data` if it has a CF_HTML header, otherwise treats it as plain HTML.
+    pub fn decode(data: &str) -> Result<Self, Error> {
+        if data.trim_start().starts_with("Version:") {
+            CfHtml::parse(data)
+        } else {
+            Ok(CfHtml::new(data))
+        }
+    }
+
+    /// Parses a payload with a CF_HTML header.
+    pub fn parse(data: &str) -> Result<Self, Error> {
+        // the payload may be followed by NUL padding
+        let data = data.trim_end_matches('\0');
+        let header = Header::parse(data)?;
+
+        let version = header
+            .version
+            .ok_or_else(|| Error::new("CF_HTML header has no Version"))?;
+        let start_fragment = header
+            .start_fragment
+            .ok_or_else(|| Error::new("CF_HTML header has no StartFragment"))?;
+        let end_fragment = header
+            .end_fragment
+            .ok_or_else(|| Error::new("CF_HTML header has no EndFragment"))?;
+
+        // StartHTML and EndHTML are -1 when there is no context
+        let start_html = match header.start_html {
+            Some(n) if n >= 0 => n,
+            _ => header.len as i64,
+        };
+        let end_html = match header.end_html {
+            Some(n) if n >= 0 => n,
+            _ => data.len() as i64,
+        };
+
+        let html = offset_range(data, "HTML", start_html, end_html)?;
+        let fragment = offset_range(data, "Fragment", start_fragment, end_fragment)?;
+        if fragment.start < html.start || html.end < fragment.end {
+            return Err(Error::new(format!(
+                "CF_HTML fragment {:?} is outside of HTML {:?}",
+                fragment, html
+            )));
+        }
+
+        let selection = match (header.start_selection, header.end_selection) {
+            (Some(start), Some(end)) if start >= 0 && end >= 0 => {
+                let selection = offset_range(data, "Selection", start, end)?;
+                if selection.start < html.start || html.end < selection.end {
+                    return Err(Error::new(format!(
+                        "CF_HTML selection {:?} is outside of HTML {:?}",
+                        selection, html
+                    )));
+                }
+                Some((selection.start - html.start)..(selection.end - html.start))
+            }
+            (None, None) => None,
+            (Some(_), Some(_)) => None,
+            _ => {
+                return Err(Error::new(
+                    "CF_HTML header needs both StartSelection and EndSelection",
+                ));
+            }
+        };
+
+        Ok(Self {
+            version,
+            source_url: header.source_url,
+            html: data[html.clone()].to_string(),
+            fragment: (fragment.start - html.start)..(fragment.end - html.start),
+            selection,
+        })
+    }
+
+    /// Encodes the payload with a CF_HTML header.
+    pub fn encode(&self) -> String {
+        // offsets are zero padded, so the header length does not depend on them
+        let header_len = self.header(0).len();
+        format!("{}{}", self.header(header_len), self.html)
+    }
+
+    fn header(&self, offset: usize) -> String {
+        let mut lines = vec![
+            format!("Version:{}", self.version),
+            format!("StartHTML:{:0>10}", offset),
+            format!("EndHTML:{:0>10}", offset + self.html.len()),
+            format!("StartFragment:{:0>10}", offset + self.fragment.start),
+            format!("EndFragment:{:0>10}", offset + self.fragment.end),
+        ];
+
+        if let Some(selection) = &self.selection {
+            lines.push(format!("StartSelection:{:0>10}", offset + selection.start));
+            lines.push(format!("EndSelection:{:0>10}", offset + selection.end));
+        }
+
+        if let Some(source_url) = &self.source_url {
+            lines.push(format!("SourceURL:{}", source_url));
+        }
+
+        lines.push(String::new());
+        lines.join("\r\n")
+    }
+
+    pub fn version(&self) -> &str {
+        &self.version
+    }
+
+    pub fn source_url(&self) -> Option<&str> {
+        self.source_url.as_deref()
+    }
+
+    pub fn set_source_url(&mut self, source_url: Option<String>) {
+        self.source_url = source_url;
+    }
+
+    /// StartHTML..EndHTML
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    /// StartFragment..EndFragment
+    pub fn fragment(&self) -> &str {
+        &self.html[self.fragment.clone()]
+    }
+
+    /// StartSelection..EndSelection, if the header has them
+    pub fn selection(&self) -> Option<&str> {
+        self.selection.as_ref().map(|r| &self.html[r.clone()])
+    }
+}
+
+impl Header {
+    fn parse(data: &str) -> Result<Self, Error> {
+        let mut header = Header::default();
+
+        let mut pos = 0;
+        while pos < data.len() {
+            let rest = &data[pos..];
+            if rest.starts_with('<') {
+                break;
+            }
+
+            let line_len = rest.find(['\r', '\n']).unwrap_or(rest.len());
+            let line = &rest[..line_len];
+
+            let (key, value) = match line.split_once(':') {
+                Some(kv) => kv,
+                None => break,
+            };
+
+            match key {
+                "Version" => header.version = Some(value.to_string()),
+                "StartHTML" => header.start_html = Some(parse_offset(key, value)?),
+                "EndHTML" => header.end_html = Some(parse_offset(key, value)?),
+                "StartFragment" => header.start_fragment = Some(parse_offset(key, value)?),
+                "EndFragment" => header.end_fragment = Some(parse_offset(key, value)?),
+                "StartSelection" => header.start_selection = Some(parse_offset(key, value)?),
+                "EndSelection" => header.end_selection = Some(parse_offset(key, value)?),
+                "SourceURL" => header.source_url = Some(value.to_string()),
+                // unknown descriptions are ignored
+                _ => {}
+            }
+
+            pos += line_len;
+            if data[pos..].starts_with("\r\n") {
+                pos += 2;
+            } else if data[pos..].starts_with(['\r', '\n']) {
+                pos += 1;
+            }
+        }
+
+        header.len = pos;
+        Ok(header)
+    }
+}
+
+fn parse_offset(key: &str, value: &str) -> Result<i64, Error> {
+    value
+        .trim()
+        .parse::<i64>()
+        .map_err(|e| Error::new(format!("CF_HTML {} is not a number: {}", key, e)))
+}
+
+fn offset_range(data: &str, name: &str, start: i64, end: i64) -> Result<Range<usize>, Error> {
+    if start < 0 || end < start || end as usize > data.len() {
+        return Err(Error::new(format!(
+            "CF_HTML Start{}:{} / End{}:{} is out of range (length: {})",
+            name,
+            start,
+            name,
+            end,
+            data.len()
+        )));
+    }
+
+    let (start, end) = (start as usize, end as usize);
+    for offset in [start, end] {
+        if !data.is_char_boundary(offset) {
+            return Err(Error::new(format!(
+                "CF_HTML offset {} is not on a UTF-8 character boundary",
+                offset
+            )));
+        }
+    }
+
+    Ok(start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_and_parse() {
+        let html = "<html><body><!--StartFragment--><b>ほげ</b><!--EndFragment--></body></html>";
+        let mut cf_html = CfHtml::new(html);
+        cf_html.set_source_url(Some("https://example.com/".to_string()));
+
+        let encoded = cf_html.encode();
+        assert!(encoded.starts_with("Version:0.9\r\nStartHTML:"));
+
+        let parsed = CfHtml::parse(&encoded).unwrap();
+        assert_eq!(cf_html, parsed);
+        assert_eq!(html, parsed.html());
+        assert_eq!("<b>ほげ</b>", parsed.fragment());
+        assert_eq!(Some("https://example.com/"), parsed.source_url());
+        assert_eq!(None, parsed.selection());
+    }
+
+    #[test]
+    fn test_new_without_markers() {
+        let cf_html = CfHtml::new("<b>foo</b>");
+
+        assert_eq!("<b>foo</b>", cf_html.html());
+        assert_eq!("<b>foo</b>", cf_html.fragment());
+
+        let parsed = CfHtml::parse(&cf_html.encode()).unwrap();
+        assert_eq!("<b>foo</b>", parsed.fragment());
+    }
+
+    #[test]
+    fn test_parse_chrome() {
+        let html = "<html>\r\n<body>\r\n<!--StartFragment--><a href=\"https://example.com/\">link</a><!--EndFragment-->\r\n</body>\r\n</html>";
+        let start_html = 190;
+        let start_fragment = start_html + html.find("<a").unwrap();
+        let end_fragment = start_html + html.find("<!--End").unwrap();
+        let start_selection = start_fragment + "<a href=\"https://example.com/\">".len();
+        let data = format!(
+            "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\nStartSelection:{:010}\r\nEndSelection:{:010}\r\nSourceURL:https://example.com/a\r\n{}\0\0",
+            start_html,
+            start_html + html.len(),
+            start_fragment,
+            end_fragment,
+            start_selection,
+            start_selection + "link".len(),
+            html
+        );
+        assert_eq!(start_html, data.find("<html>").unwrap());
+
+        let cf_html = CfHtml::parse(&data).unwrap();
+        assert_eq!("0.9", cf_html.version());
+        assert_eq!(html, cf_html.html());
+        assert_eq!(
+            "<a href=\"https://example.com/\">link</a>",
+            cf_html.fragment()
+        );
+        assert_eq!(Some("link"), cf_html.selection());
+        assert_eq!(Some("https://example.com/a"), cf_html.source_url());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        // missing fragment
+        assert!(CfHtml::parse("Version:0.9\r\nStartHTML:0000000010\r\n<html></html>").is_err());
+
+        // out of range
+        let data = "Version:0.9\r\nStartHTML:0000000001\r\nEndHTML:0000009999\r\nStartFragment:0000000001\r\nEndFragment:0000000002\r\n<html></html>";
+        assert!(CfHtml::parse(data).is_err());
+
+        // not a number
+        assert!(CfHtml::parse("Version:0.9\r\nStartHTML:abc\r\n").is_err());
+
+        // in the middle of a multibyte character
+        let html = "<p>あ</p>";
+        let header = "Version:0.9\r\nStartHTML:0000000000\r\nEndHTML:0000000000\r\nStartFragment:0000000000\r\nEndFragment:0000000000\r\n";
+        let data = format!(
+            "Version:0.9\r\nStartHTML:{:010}\r\nEndHTML:{:010}\r\nStartFragment:{:010}\r\nEndFragment:{:010}\r\n{}",
+            header.len(),
+            header.len() + html.len(),
+            header.len() + 4,
+            header.len() + 5,
+            html
+        );
+        assert!(CfHtml::parse(&data).is_err());
+    }
+
+    #[test]
+    fn test_decode_plain_html() {
+        let cf_html = CfHtml::decode("<meta charset='utf-8'><b>foo</b>").unwrap();
+        assert_eq!("<meta charset='utf-8'><b>foo</b>", cf_html.fragment());
+        assert_eq!(None, cf_html.source_url());
+    }
+}
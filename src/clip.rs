@@ -4,6 +4,7 @@ use crate::error::Error;
 pub enum ClipboardFormat {
     Text(String),
     Html(String),
+    Rtf(String),
 }
 
 impl ToString for ClipboardFormat {
@@ -11,6 +12,7 @@ impl ToString for ClipboardFormat {
         match self {
             ClipboardFormat::Text(s) => s,
             ClipboardFormat::Html(s) => s,
+            ClipboardFormat::Rtf(s) => s,
         }
         .to_string()
     }
@@ -22,6 +24,12 @@ impl Default for ClipboardFormat {
     }
 }
 
+impl ClipboardFormat {
+    pub fn is_same_format(&self, other: &ClipboardFormat) -> bool {
+        std::mem::discriminant(self) == std::mem::discriminant(other)
+    }
+}
+
 pub struct Clipboard {}
 
 pub trait Clip {
@@ -30,45 +38,182 @@ pub trait Clip {
     fn determine_format(&self) -> Result<ClipboardFormat, Error>;
 
     fn get_data(&mut self, format: &ClipboardFormat) -> Result<ClipboardFormat, Error>;
-    fn set_data(&mut self, data: &ClipboardFormat) -> Result<(), Error>;
 
-    fn get_html<T: ToString>(data: &T) -> String;
+    fn set_data(&mut self, data: &ClipboardFormat) -> Result<(), Error> {
+        self.set_data_list(std::slice::from_ref(data))
+    }
+
+    /// Replaces the clipboard with every format in `data` at once,
+    /// so that pasting picks the richest one the target understands.
+    fn set_data_list(&mut self, data: &[ClipboardFormat]) -> Result<(), Error>;
 }
 
 #[cfg(target_os = "linux")]
 pub mod clipboard {
-    use crate::error::Error;
+    use crate::{error::Error, wayland_clipboard::WaylandClipboard, x11_clipboard::X11Clipboard};
 
     use super::{Clip, Clipboard, ClipboardFormat};
 
+    const HTML_TARGET: &str = "text/html";
+    const RTF_TARGETS: [&str; 2] = ["text/rtf", "application/rtf"];
+    const TEXT_TARGETS: [&str; 5] = [
+        "text/plain;charset=utf-8",
+        "UTF8_STRING",
+        "text/plain",
+        "STRING",
+        "TEXT",
+    ];
+
+    enum Backend {
+        Wayland(WaylandClipboard),
+        X11(Box<X11Clipboard>),
+    }
+
+    impl Backend {
+        fn connect() -> Result<Self, Error> {
+            if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+                match WaylandClipboard::new() {
+                    Ok(c) => return Ok(Backend::Wayland(c)),
+                    // e.g. GNOME does not implement data-control, try XWayland
+                    Err(e) if std::env::var_os("DISPLAY").is_none() => return Err(e),
+                    Err(_) => {}
+                }
+            }
+
+            if std::env::var_os("DISPLAY").is_some() {
+                return Ok(Backend::X11(Box::new(X11Clipboard::new()?)));
+            }
+
+            Err(Error::new("Neither WAYLAND_DISPLAY nor DISPLAY is set"))
+        }
+
+        fn targets(&self) -> Result<Vec<String>, Error> {
+            match self {
+                Backend::Wayland(c) => c.targets(),
+                Backend::X11(c) => c.targets(),
+            }
+        }
+
+        fn read(&self, target: &str) -> Result<Option<Vec<u8>>, Error> {
+            match self {
+                Backend::Wayland(c) => c.read(target),
+                Backend::X11(c) => c.read(target),
+            }
+        }
+
+        fn write(&self, contents: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+            match self {
+                Backend::Wayland(c) => c.write(contents),
+                Backend::X11(c) => c.write(contents),
+            }
+        }
+    }
+
+    impl Clipboard {
+        /// Reads the first of `candidates` the owner offers.
+        fn read_any(backend: &Backend, candidates: &[&str]) -> Result<Option<Vec<u8>>, Error> {
+            let targets = backend.targets()?;
+            match candidates.iter().find(|t| targets.iter().any(|e| e == *t)) {
+                Some(target) => backend.read(target),
+                None => Ok(None),
+            }
+        }
+
+        fn decode(data: &[u8]) -> String {
+            // Firefox offers text/html as UTF-16 with BOM
+            match data {
+                [0xff, 0xfe, rest @ ..] => {
+                    let utf16 = rest
+                        .chunks_exact(2)
+                        .map(|c| u16::from_le_bytes([c[0], c[1]]))
+                        .collect::<Vec<u16>>();
+                    String::from_utf16_lossy(&utf16)
+                }
+                [0xfe, 0xff, rest @ ..] => {
+                    let utf16 = rest
+                        .chunks_exact(2)
+                        .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                        .collect::<Vec<u16>>();
+                    String::from_utf16_lossy(&utf16)
+                }
+                _ => String::from_utf8_lossy(data).to_string(),
+            }
+        }
+    }
+
     impl Clip for Clipboard {
         fn new() -> Self {
             Self {}
         }
 
         fn get_data(&mut self, format: &ClipboardFormat) -> Result<ClipboardFormat, Error> {
-            Ok(ClipboardFormat::Text("".to_owned()))
-        }
+            let backend = Backend::connect()?;
 
-        fn set_data(&mut self, data: &ClipboardFormat) -> Result<(), Error> {
-            Ok(())
+            let data = match format {
+                ClipboardFormat::Html(_) => backend.read(HTML_TARGET)?,
+                ClipboardFormat::Text(_) => Clipboard::read_any(&backend, &TEXT_TARGETS)?,
+                ClipboardFormat::Rtf(_) => Clipboard::read_any(&backend, &RTF_TARGETS)?,
+            };
+
+            let str_data = match data {
+                Some(d) => Clipboard::decode(&d),
+                None => return Err(Error::new("Clipboard format not available")),
+            };
+
+            Ok(match format {
+                ClipboardFormat::Text(_) => ClipboardFormat::Text(str_data),
+                ClipboardFormat::Html(_) => ClipboardFormat::Html(str_data),
+                ClipboardFormat::Rtf(_) => ClipboardFormat::Rtf(str_data),
+            })
         }
 
-        fn get_html<T>(data: &T) -> String {
-            String::default()
+        fn set_data_list(&mut self, data: &[ClipboardFormat]) -> Result<(), Error> {
+            let backend = Backend::connect()?;
+
+            let contents = data
+                .iter()
+                .flat_map(|d| match d {
+                    ClipboardFormat::Text(s) => TEXT_TARGETS
+                        .iter()
+                        .map(|t| (t.to_string(), s.clone().into_bytes()))
+                        .collect::<Vec<_>>(),
+                    ClipboardFormat::Html(s) => {
+                        vec![(HTML_TARGET.to_string(), s.clone().into_bytes())]
+                    }
+                    ClipboardFormat::Rtf(s) => RTF_TARGETS
+                        .iter()
+                        .map(|t| (t.to_string(), s.clone().into_bytes()))
+                        .collect::<Vec<_>>(),
+                })
+                .collect();
+
+            backend.write(contents)
         }
 
         fn determine_format(&self) -> Result<ClipboardFormat, Error> {
-            Ok(ClipboardFormat::Text("".to_owned()))
+            let targets = Backend::connect()?.targets()?;
+
+            // HTML
+            if targets.iter().any(|t| t == HTML_TARGET) {
+                return Ok(ClipboardFormat::Html("".to_string()));
+            }
+
+            // Plain text
+            if targets.iter().any(|t| TEXT_TARGETS.contains(&t.as_str())) {
+                return Ok(ClipboardFormat::Text("".to_string()));
+            }
+
+            // unsupported
+            Err(Error::new("Clipboard format not available"))
         }
     }
 }
 
 #[cfg(target_os = "windows")]
 pub mod clipboard {
-    use regex::Regex;
-
-    use crate::{error::Error, global_memory::GlobalMemory, win_clipboard::WinClipboard};
+    use crate::{
+        cf_html::CfHtml, error::Error, global_memory::GlobalMemory, win_clipboard::WinClipboard,
+    };
 
     use super::{Clip, Clipboard, ClipboardFormat};
 
@@ -77,6 +222,7 @@ pub mod clipboard {
             match format {
                 ClipboardFormat::Text(_) => WinClipboard::new_with_unicode_text(),
                 ClipboardFormat::Html(_) => WinClipboard::new_wth_html_text(),
+                ClipboardFormat::Rtf(_) => WinClipboard::new_with_rtf(),
             }
         }
 
@@ -85,65 +231,57 @@ pub mod clipboard {
             match format {
                 // reduce last \0
                 ClipboardFormat::Text(_) => String::from_utf16(&slice[..slice.len() - 1]).unwrap(),
-                ClipboardFormat::Html(_) => {
+                ClipboardFormat::Html(_) | ClipboardFormat::Rtf(_) => {
                     let mut utf8_vec: Vec<u8> = vec![];
                     slice.iter().for_each(|e| {
                         utf8_vec.push(*e as u8);
                         utf8_vec.push((e >> 8) as u8);
                     });
-                    // reduce last \0
-                    String::from_utf8_lossy(&utf8_vec[..&utf8_vec.len() - 1]).to_string()
+                    // reduce trailing \0
+                    String::from_utf8_lossy(&utf8_vec)
+                        .trim_end_matches('\0')
+                        .to_string()
                 }
             }
         }
 
-        fn append_clipboard_data<T>(data: &T) -> String
-        where
-            T: ToString,
-        {
-            let binding = data.to_string();
-            let result = vec![
-                "Version:0.9",
-                "StartHTML:0000000000",
-                "EndHTML:0000000000",
-                "StartFragment:0000000000",
-                "EndFragment:0000000000",
-            ];
-
-            let tmp = result.join("\r\n");
-
-            let header_len = tmp.len() + "\r\n".len();
-            // <html><body><!--StartFragment-->
-            let start_offset = (1 + 4 + 1) + (1 + 4 + 1) + (4 + 5 + 8 + 3);
-            // <!--EndFragment--></body></html>
-            let end_offset = (4 + 3 + 8 + 3) + (2 + 4 + 1) + (2 + 4 + 1);
-
-            let start_html = header_len;
-            let end_html = start_html + start_offset + binding.len() + end_offset;
-            let start_fragment = header_len + start_offset;
-            let end_fragment = start_fragment + binding.len();
-
-            let result = vec![
-                format!("Version:{}", "0.9"),
-                format!("StartHTML:{:0>10}", start_html),
-                format!("EndHTML:{:0>10}", end_html),
-                format!("StartFragment:{:0>10}", start_fragment),
-                format!("EndFragment:{:0>10}", end_fragment),
-                binding,
-            ];
-
-            result.join("\r\n")
-        }
+        fn encode(data: &ClipboardFormat) -> Result<GlobalMemory, Error> {
+            let (src_str, char_size) = match data {
+                ClipboardFormat::Text(s) => (s.to_owned(), 16),
+                ClipboardFormat::Html(s) => (CfHtml::new(s).encode(), 8),
+                ClipboardFormat::Rtf(s) => (s.to_owned(), 8),
+            };
 
-        // fn encode<T>(data: &ClipboardFormat) -> Vec<T>
-        // where
-        //     T: Sized,
-        // {
-        //     match data {
-        //         ClipboardFormat::Text(s) => s.encode_utf16().collect(),
-        //         ClipboardFormat::Html(s) => s.encode_utf16().collect(),
-        //     }
-        // }
+            let global_size = (src_str.len() + 1) * char_size;
+
+            let mut mem = GlobalMemory::new();
+            let ptr = match mem.alloc_without_free(global_size) {
+                Ok(ptr) => ptr,
+                Err(e) => {
+                    return Err(Error::new(format!(
+                        "Failed to allocate memory: {}",
+                        e.to_string()
+                    )));
+                }
+            };
+
+            match data {
+                ClipboardFormat::Text(_) => {
+                    let src = src_str.encode_utf16().collect::<Vec<u16>>();
+                    unsafe {
+                        std::ptr::copy(src.as_ptr(), ptr as *mut u16, src.len());
+                    }
+                }
+                ClipboardFormat::Html(_) | ClipboardFormat::Rtf(_) => {
+                    let src = src_str.into_bytes();
+                    unsafe {
+                        std::ptr::copy(src.as_ptr(), ptr as *mut u8, src.len());
+                    }
+                }
+            };
+
+            Ok(mem)
+        }
     }
 
     impl Clip for Clipboard {
@@ -176,93 +314,29 @@ pub mod clipboard {
             Ok(match format {
                 ClipboardFormat::Text(_) => ClipboardFormat::Text(str_data),
                 ClipboardFormat::Html(_) => ClipboardFormat::Html(str_data),
+                ClipboardFormat::Rtf(_) => ClipboardFormat::Rtf(str_data),
             })
         }
 
-        fn set_data(&mut self, data: &ClipboardFormat) -> Result<(), Error> {
-            let (src_str, char_size) = match data {
-                ClipboardFormat::Text(s) => (s.to_owned(), 16),
-                ClipboardFormat::Html(s) => (Clipboard::append_clipboard_data(s), 8),
-            };
+        fn set_data_list(&mut self, data: &[ClipboardFormat]) -> Result<(), Error> {
+            // resolve the formats before taking the clipboard
+            let formats = data
+                .iter()
+                .map(|d| Clipboard::create_instance_by(d).format())
+                .collect::<Vec<_>>();
 
-            let mut instance = Clipboard::create_instance_by(data);
+            let mut instance = WinClipboard::new_with_unicode_text();
 
             instance.open()?;
             instance.empty()?;
 
-            let global_size = (src_str.len() + 1) * char_size;
-
-            let mut mem = GlobalMemory::new();
-            let ptr = match mem.alloc_without_free(global_size) {
-                Ok(ptr) => ptr,
-                Err(e) => {
-                    return Err(Error::new(format!(
-                        "Failed to allocate memory: {}",
-                        e.to_string()
-                    )));
-                }
-            };
-
-            match data {
-                ClipboardFormat::Text(_) => {
-                    let src = src_str.encode_utf16().collect::<Vec<u16>>();
-                    unsafe {
-                        std::ptr::copy(src.as_ptr(), ptr as *mut u16, src.len());
-                    }
-                }
-                ClipboardFormat::Html(_) => {
-                    let src = src_str.into_bytes();
-                    unsafe {
-                        std::ptr::copy(src.as_ptr(), ptr as *mut u8, src.len());
-                    }
-                }
-            };
-
-            Ok(instance.set_clipboard_data(mem.get_global())?)
-        }
-
-        fn get_html<T>(data: &T) -> String
-        where
-            T: ToString,
-        {
-            // const RE_VERSION_PATTERN: &'static str = r"^Version:([0-9\.]+)$";
-            const RE_START_HTML_PATTERN: &'static str = r"^StartHTML:([0-9]+)$";
-            const RE_END_HTML_PATTERN: &'static str = r"^EndHTML:([0-9]+)$";
-
-            // let re_version = Regex::new(RE_VERSION_PATTERN).unwrap();
-            let re_start_html = Regex::new(RE_START_HTML_PATTERN).unwrap();
-            let re_end_html = Regex::new(RE_END_HTML_PATTERN).unwrap();
-
-            let mut start = 0;
-            let mut end = 0;
-
-            for raw_line in data.to_string().lines() {
-                let line = raw_line.trim();
-                if line.is_empty() {
-                    continue;
-                }
-
-                if start <= 0 {
-                    start = match re_start_html.captures(line) {
-                        Some(c) => c.get(1).unwrap().as_str().parse::<usize>().unwrap(),
-                        None => {
-                            continue;
-                        }
-                    }
-                };
-
-                if end <= 0 {
-                    end = match re_end_html.captures(line) {
-                        Some(c) => c.get(1).unwrap().as_str().parse::<usize>().unwrap(),
-                        None => {
-                            continue;
-                        }
-                    }
-                };
+            for (d, format) in data.iter().zip(formats) {
+                // keep the memory until the clipboard takes it
+                let mem = Clipboard::encode(d)?;
+                instance.set_clipboard_data_as(format, mem.get_global())?;
             }
 
-            let s = data.to_string();
-            String::from(s.get(start..(end - 2)).unwrap())
+            Ok(())
         }
 
         fn determine_format(&self) -> Result<ClipboardFormat, Error> {
@@ -0,0 +1,158 @@
+//! Lua userdata for the parsed HTML tree
+//!
+//! # Example
+//! ```lua
+//! for _, child in ipairs(qlp.dom.children) do
+//!     print(child.tag, child:text())
+//! end
+//! ```
+
+use std::rc::Rc;
+
+use markup5ever_rcdom::{Handle, NodeData};
+use mlua::{MetaMethod, UserData, UserDataFields, UserDataMethods, UserDataRef};
+
+use crate::{
+    html::{get_attrs, get_parent, get_text, html_children_to_string, html_handle_to_string},
+    selector::Selector,
+};
+
+#[derive(Clone)]
+pub struct LuaNode(pub Handle);
+
+impl LuaNode {
+    fn node_type(&self) -> &'static str {
+        match self.0.data {
+            NodeData::Document => "document",
+            NodeData::Doctype { .. } => "doctype",
+            NodeData::Text { .. } => "text",
+            NodeData::Comment { .. } => "comment",
+            NodeData::Element { .. } => "element",
+            NodeData::ProcessingInstruction { .. } => "processing_instruction",
+        }
+    }
+
+    fn tag(&self) -> Option<String> {
+        match self.0.data {
+            NodeData::Element { ref name, .. } => Some(name.local.to_string()),
+            _ => None,
+        }
+    }
+
+    fn attrs(&self) -> Vec<(String, String)> {
+        get_attrs(&self.0)
+    }
+
+    fn children(&self) -> Vec<LuaNode> {
+        self.0
+            .children
+            .borrow()
+            .iter()
+            .map(|c| LuaNode(c.clone()))
+            .collect()
+    }
+
+    fn parent(&self) -> Option<LuaNode> {
+        get_parent(&self.0).map(LuaNode)
+    }
+
+    pub fn text(&self) -> String {
+        match self.0.data {
+            NodeData::Text { ref contents } => contents.borrow().to_string(),
+            NodeData::Comment { ref contents } => contents.to_string(),
+            _ => get_text(&self.0),
+        }
+    }
+
+    fn inner_html(&self) -> String {
+        html_children_to_string(&self.0)
+    }
+
+    /// Returns the descendants matching the CSS selector `css`.
+    pub fn select(&self, css: &str) -> mlua::Result<Vec<LuaNode>> {
+        let selector =
+            Selector::parse(css).map_err(|e| mlua::Error::RuntimeError(e.to_string()))?;
+
+        Ok(selector.select(&self.0).into_iter().map(LuaNode).collect())
+    }
+
+    fn outer_html(&self) -> String {
+        match self.0.data {
+            // a document cannot be serialized by itself
+            NodeData::Document => html_children_to_string(&self.0),
+            _ => html_handle_to_string(&self.0),
+        }
+    }
+}
+
+impl UserData for LuaNode {
+    fn add_fields<F: UserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("type", |_, this| Ok(this.node_type()));
+        fields.add_field_method_get("tag", |_, this| Ok(this.tag()));
+        fields.add_field_method_get("attrs", |lua, this| lua.create_table_from(this.attrs()));
+        fields.add_field_method_get("children", |_, this| Ok(this.children()));
+        fields.add_field_method_get("parent", |_, this| Ok(this.parent()));
+    }
+
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("text", |_, this, ()| Ok(this.text()));
+        methods.add_method("inner_html", |_, this, ()| Ok(this.inner_html()));
+        methods.add_method("outer_html", |_, this, ()| Ok(this.outer_html()));
+        methods.add_method("select", |_, this, css: String| this.select(&css));
+
+        methods.add_meta_method(MetaMethod::Eq, |_, this, other: UserDataRef<LuaNode>| {
+            Ok(Rc::ptr_eq(&this.0, &other.0))
+        });
+        methods.add_meta_method(MetaMethod::ToString, |_, this, ()| Ok(this.outer_html()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::parse_html;
+
+    #[test]
+    fn test_lua_node() {
+        let html = r#"<html><body><h1 id="title">Title</h1><ul><li><a href="https://example.com/">foo</a></li><li>bar</li></ul></body></html>"#;
+        let dom = parse_html(&html.to_string());
+
+        let lua = mlua::Lua::new();
+        lua.globals()
+            .set("dom", LuaNode(dom.document.clone()))
+            .unwrap();
+
+        lua.load(
+            r#"
+            local html = dom.children[1]
+            assert(dom.type == "document")
+            assert(html.tag == "html")
+            assert(html.parent == dom)
+
+            local body = html.children[2]
+            local h1 = body.children[1]
+            assert(h1.tag == "h1")
+            assert(h1.attrs.id == "title")
+            assert(h1:text() == "Title")
+            assert(h1.children[1].type == "text")
+            assert(h1.children[1].tag == nil)
+
+            local ul = body.children[2]
+            assert(#ul.children == 2)
+            assert(ul:text() == "foobar")
+            assert(ul:inner_html() == '<li><a href="https://example.com/">foo</a></li><li>bar</li>')
+            assert(ul.children[2]:outer_html() == "<li>bar</li>")
+            assert(tostring(ul.children[2]) == "<li>bar</li>")
+            assert(ul.children[1].children[1].attrs.href == "https://example.com/")
+
+            local links = dom:select("ul > li a[href]")
+            assert(#links == 1)
+            assert(links[1]:text() == "foo")
+            assert(#ul:select("li") == 2)
+            assert(not pcall(function() return dom:select("a[") end))
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+}
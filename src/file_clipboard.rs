@@ -0,0 +1,142 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    clip::{Clip, ClipboardFormat},
+    error::Error,
+};
+
+const DEFAULT_DIR: &str = "clipboard";
+
+/// Clipboard backed by a directory, one file per format.
+///
+/// ```text
+/// <dir>/
+///   +-- text.txt
+///   +-- html.html  (plain HTML or a CF_HTML payload)
+///   `-- rtf.rtf
+/// ```
+pub struct FileClipboard {
+    dir: PathBuf,
+}
+
+impl FileClipboard {
+    pub fn with_dir<P: AsRef<Path>>(dir: P) -> Self {
+        Self {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn file_name(format: &ClipboardFormat) -> &'static str {
+        match format {
+            ClipboardFormat::Text(_) => "text.txt",
+            ClipboardFormat::Html(_) => "html.html",
+            ClipboardFormat::Rtf(_) => "rtf.rtf",
+        }
+    }
+
+    fn path_of(&self, format: &ClipboardFormat) -> PathBuf {
+        self.dir.join(FileClipboard::file_name(format))
+    }
+
+    fn all_formats() -> Vec<ClipboardFormat> {
+        vec![
+            ClipboardFormat::Html("".to_string()),
+            ClipboardFormat::Rtf("".to_string()),
+            ClipboardFormat::Text("".to_string()),
+        ]
+    }
+}
+
+impl Clip for FileClipboard {
+    fn new() -> Self {
+        Self::with_dir(DEFAULT_DIR)
+    }
+
+    fn determine_format(&self) -> Result<ClipboardFormat, Error> {
+        // HTML is preferred over plain text, RTF is only written
+        match FileClipboard::all_formats()
+            .into_iter()
+            .filter(|f| !matches!(f, ClipboardFormat::Rtf(_)))
+            .find(|f| self.path_of(f).is_file())
+        {
+            Some(format) => Ok(format),
+            None => Err(Error::new("Clipboard format not available")),
+        }
+    }
+
+    fn get_data(&mut self, format: &ClipboardFormat) -> Result<ClipboardFormat, Error> {
+        let path = self.path_of(format);
+        if !path.is_file() {
+            return Err(Error::new("Clipboard format not available"));
+        }
+
+        let str_data = fs::read_to_string(&path)
+            .map_err(|e| Error::new(format!("Failed to read {}: {}", path.display(), e)))?;
+
+        Ok(match format {
+            ClipboardFormat::Text(_) => ClipboardFormat::Text(str_data),
+            ClipboardFormat::Html(_) => ClipboardFormat::Html(str_data),
+            ClipboardFormat::Rtf(_) => ClipboardFormat::Rtf(str_data),
+        })
+    }
+
+    fn set_data_list(&mut self, data: &[ClipboardFormat]) -> Result<(), Error> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| Error::new(format!("Failed to create {}: {}", self.dir.display(), e)))?;
+
+        // like emptying the system clipboard
+        for format in FileClipboard::all_formats() {
+            let path = self.path_of(&format);
+            if path.is_file() {
+                fs::remove_file(&path).map_err(|e| {
+                    Error::new(format!("Failed to remove {}: {}", path.display(), e))
+                })?;
+            }
+        }
+
+        for d in data {
+            let path = self.path_of(d);
+            fs::write(&path, d.to_string())
+                .map_err(|e| Error::new(format!("Failed to write {}: {}", path.display(), e)))?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_clipboard() {
+        let dir = std::env::temp_dir().join(format!("qlp-file-clipboard-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut clip = FileClipboard::with_dir(&dir);
+        assert!(clip.determine_format().is_err());
+
+        clip.set_data(&ClipboardFormat::Html("<b>foo</b>".to_string()))
+            .unwrap();
+        assert!(dir.join("html.html").is_file());
+        assert!(matches!(
+            clip.determine_format().unwrap(),
+            ClipboardFormat::Html(_)
+        ));
+
+        clip.set_data(&ClipboardFormat::Text("bar".to_string()))
+            .unwrap();
+        assert!(!dir.join("html.html").exists());
+        assert_eq!(
+            "bar",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
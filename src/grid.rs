@@ -0,0 +1,641 @@
+//! Cell ranges copied from spreadsheets
+//!
+//! Excel, LibreOffice Calc and Google Sheets put a range on the clipboard both as an HTML table
+//! and as tab-separated text. `qlp.grid` reads either of them into rows of typed values,
+//! `qlp.result_grid` writes rows back as both.
+
+use std::fmt;
+
+use csv::{QuoteStyle, ReaderBuilder, Terminator, WriterBuilder};
+use markup5ever_rcdom::{Handle, RcDom};
+use mlua::{Lua, Table, Value};
+
+use crate::{
+    blocks::collapse_whitespace,
+    html::{append_child, create_element, create_text, get_grid, get_rows, get_tables, get_text},
+    utils::lua_value_to_text,
+};
+
+const CURRENCY_SYMBOLS: &[char] = &['$', '€', '£', '¥', '￥', '₩'];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CellValue {
+    Empty,
+    Bool(bool),
+    Integer(i64),
+    Number(f64),
+    Date {
+        year: i64,
+        month: i64,
+        day: i64,
+        time: Option<(i64, i64, i64)>,
+    },
+    Text(String),
+}
+
+impl CellValue {
+    /// Infers the type of a cell from the text a spreadsheet shows.
+    pub fn infer(text: &str) -> CellValue {
+        let trimmed = text.trim();
+
+        if trimmed.is_empty() {
+            return CellValue::Empty;
+        }
+
+        if trimmed.eq_ignore_ascii_case("true") {
+            return CellValue::Bool(true);
+        }
+        if trimmed.eq_ignore_ascii_case("false") {
+            return CellValue::Bool(false);
+        }
+
+        parse_date(trimmed)
+            .or_else(|| parse_number(trimmed))
+            .unwrap_or_else(|| CellValue::Text(text.to_string()))
+    }
+}
+
+/// A cell with the text the spreadsheet showed, written back as it was unless a script changes it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cell {
+    pub value: CellValue,
+    /// `None` for values set by scripts
+    pub text: Option<String>,
+}
+
+impl Cell {
+    pub fn infer(text: &str) -> Cell {
+        Cell {
+            value: CellValue::infer(text),
+            text: Some(text.to_string()),
+        }
+    }
+}
+
+impl From<CellValue> for Cell {
+    fn from(value: CellValue) -> Self {
+        Cell { value, text: None }
+    }
+}
+
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.text {
+            Some(text) => write!(f, "{}", text),
+            None => write!(f, "{}", self.value),
+        }
+    }
+}
+
+impl fmt::Display for CellValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CellValue::Empty => Ok(()),
+            // as spreadsheets show them, so that pasting infers them again
+            CellValue::Bool(true) => write!(f, "TRUE"),
+            CellValue::Bool(false) => write!(f, "FALSE"),
+            CellValue::Integer(n) => write!(f, "{}", n),
+            CellValue::Number(n) => write!(f, "{}", format_number(*n)),
+            CellValue::Date {
+                year,
+                month,
+                day,
+                time,
+            } => {
+                write!(f, "{:04}-{:02}-{:02}", year, month, day)?;
+                match time {
+                    Some((hour, min, sec)) => write!(f, " {:02}:{:02}:{:02}", hour, min, sec),
+                    None => Ok(()),
+                }
+            }
+            CellValue::Text(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+/// Formats with at most 15 significant digits, hiding binary noise such as `0.30000000000000004`.
+fn format_number(n: f64) -> String {
+    if !n.is_finite() {
+        return n.to_string();
+    }
+
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        return format!("{}", n as i64);
+    }
+
+    let magnitude = n.abs().log10().floor() as i64;
+    let decimals = (14 - magnitude).clamp(0, 20) as usize;
+    let formatted = format!("{:.*}", decimals, n);
+    if formatted.contains('.') {
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string()
+    } else {
+        formatted
+    }
+}
+
+/// Parses `1234`, `-1,234.5`, `(1,234)`, `$1,234`, `12.5%` and `1.5E+10`.
+fn parse_number(text: &str) -> Option<CellValue> {
+    let mut s = text;
+    let mut negative = false;
+
+    // accounting format
+    if let Some(inner) = s.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        negative = true;
+        s = inner.trim();
+    }
+
+    // the sign may come before or after the currency symbol
+    for _ in 0..2 {
+        if let Some(rest) = s.strip_prefix('-') {
+            negative = !negative;
+            s = rest;
+        } else if let Some(rest) = s.strip_prefix('+') {
+            s = rest;
+        }
+        s = s.trim_start_matches(CURRENCY_SYMBOLS).trim_start();
+    }
+    s = s.trim_end_matches(CURRENCY_SYMBOLS).trim_end();
+
+    let (s, percent) = match s.strip_suffix('%') {
+        Some(rest) => (rest.trim_end(), true),
+        None => (s, false),
+    };
+
+    let (mantissa, exponent) = match s.split_once(['e', 'E']) {
+        Some((m, e)) => (m, Some(e)),
+        None => (s, None),
+    };
+    let (integer, fraction) = match mantissa.split_once('.') {
+        Some((i, f)) => (i, Some(f)),
+        None => (mantissa, None),
+    };
+
+    if !is_grouped_digits(integer) {
+        return None;
+    }
+    if fraction.is_some_and(|f| !f.chars().all(|c| c.is_ascii_digit())) {
+        return None;
+    }
+    if integer.is_empty() && fraction.is_none_or(|f| f.is_empty()) {
+        return None;
+    }
+    // leading zeros are codes rather than numbers, e.g. zip codes
+    if integer.len() > 1 && integer.starts_with('0') {
+        return None;
+    }
+    if let Some(e) = exponent {
+        let digits = e.strip_prefix(['+', '-']).unwrap_or(e);
+        if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+    }
+
+    let normalized = format!(
+        "{}{}",
+        if negative { "-" } else { "" },
+        mantissa.replace(',', "")
+    );
+
+    if fraction.is_none()
+        && exponent.is_none()
+        && !percent
+        && let Ok(n) = normalized.parse::<i64>()
+    {
+        return Some(CellValue::Integer(n));
+    }
+
+    let mut n = format!(
+        "{}{}",
+        normalized,
+        exponent.map(|e| format!("e{}", e)).unwrap_or_default()
+    )
+    .parse::<f64>()
+    .ok()?;
+    if percent {
+        n /= 100.0;
+    }
+
+    Some(CellValue::Number(n))
+}
+
+/// `1234` or `1,234`, commas only between groups of three digits.
+fn is_grouped_digits(s: &str) -> bool {
+    if !s.contains(',') {
+        return s.chars().all(|c| c.is_ascii_digit());
+    }
+
+    let groups = s.split(',').collect::<Vec<_>>();
+    let first = groups[0];
+    !first.is_empty()
+        && first.len() <= 3
+        && first.chars().all(|c| c.is_ascii_digit())
+        && groups[1..]
+            .iter()
+            .all(|g| g.len() == 3 && g.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parses `2024-01-31`, `2024/1/31` and `1/31/2024`, optionally followed by `12:34` or `12:34:56`.
+fn parse_date(text: &str) -> Option<CellValue> {
+    let (date, time) = match text.split_once([' ', 'T']) {
+        Some((d, t)) => (d, Some(t.trim())),
+        None => (text, None),
+    };
+
+    let separator = if date.contains('-') { '-' } else { '/' };
+    let parts = date.split(separator).collect::<Vec<_>>();
+    if parts.len() != 3
+        || parts
+            .iter()
+            .any(|p| p.is_empty() || !p.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+
+    let (year, month, day) = match (parts[0].len(), parts[2].len()) {
+        (4, 1..=2) => (parts[0], parts[1], parts[2]),
+        // US order as Excel shows it in en-US
+        (1..=2, 4) if separator == '/' => (parts[2], parts[0], parts[1]),
+        _ => return None,
+    };
+    let (year, month, day) = (
+        year.parse::<i64>().ok()?,
+        month.parse::<i64>().ok()?,
+        day.parse::<i64>().ok()?,
+    );
+    if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+        return None;
+    }
+
+    let time = match time {
+        Some(t) => Some(parse_time(t)?),
+        None => None,
+    };
+
+    Some(CellValue::Date {
+        year,
+        month,
+        day,
+        time,
+    })
+}
+
+fn parse_time(text: &str) -> Option<(i64, i64, i64)> {
+    let parts = text.split(':').collect::<Vec<_>>();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+
+    let hour = parts[0].parse::<i64>().ok()?;
+    let min = parts[1].parse::<i64>().ok()?;
+    // fractions of a second are dropped
+    let sec = match parts.get(2) {
+        Some(s) => s.split('.').next()?.parse::<i64>().ok()?,
+        None => 0,
+    };
+
+    if !(0..24).contains(&hour) || !(0..60).contains(&min) || !(0..60).contains(&sec) {
+        return None;
+    }
+
+    Some((hour, min, sec))
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Reads the first table of the HTML, slots covered by spans are empty as in the TSV.
+pub fn grid_from_html(dom: &RcDom) -> Option<Vec<Vec<Cell>>> {
+    let table = get_tables(&dom.document).into_iter().next()?;
+    let rows = get_rows(&table);
+
+    Some(
+        get_grid(&rows)
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| match cell {
+                        Some(cell) if !cell.spanned => {
+                            Cell::infer(collapse_whitespace(&get_text(&cell.handle)).trim())
+                        }
+                        _ => Cell::infer(""),
+                    })
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Reads tab-separated text, a cell with line breaks or quotes is quoted as spreadsheets do.
+pub fn grid_from_text(text: &str) -> Vec<Vec<Cell>> {
+    let mut reader = ReaderBuilder::new()
+        .delimiter(b'\t')
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes());
+
+    let grid = reader
+        .records()
+        .map(|r| r.map(|record| record.iter().map(Cell::infer).collect()))
+        .collect::<Result<Vec<_>, _>>();
+
+    match grid {
+        Ok(grid) => grid,
+        Err(e) => {
+            // split without quotes rather than lose the rows after the error
+            eprintln!("{}", e);
+            text.lines()
+                .map(|line| line.split('\t').map(Cell::infer).collect())
+                .collect()
+        }
+    }
+}
+
+pub fn grid_to_tsv(grid: &[Vec<Cell>]) -> String {
+    let mut writer = WriterBuilder::new()
+        .delimiter(b'\t')
+        .quote_style(QuoteStyle::Necessary)
+        .terminator(Terminator::Any(b'\n'))
+        .flexible(true)
+        .from_writer(vec![]);
+
+    for row in grid {
+        writer
+            .write_record(row.iter().map(|c| c.to_string()))
+            .unwrap();
+    }
+
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+pub fn grid_to_html_table(grid: &[Vec<Cell>]) -> Handle {
+    let table = create_element("table", vec![]);
+    let tbody = create_element("tbody", vec![]);
+
+    for row in grid {
+        let tr = create_element("tr", vec![]);
+        for cell in row {
+            let attrs = match cell.value {
+                CellValue::Integer(_) | CellValue::Number(_) => {
+                    vec![("style".to_string(), "text-align: right".to_string())]
+                }
+                _ => vec![],
+            };
+
+            let td = create_element("td", attrs);
+            append_child(&td, create_text(cell.to_string()));
+            append_child(&tr, td);
+        }
+        append_child(&tbody, tr);
+    }
+
+    append_child(&table, tbody);
+    table
+}
+
+fn cell_value_to_lua(lua: &Lua, cell: &CellValue) -> mlua::Result<Value> {
+    Ok(match cell {
+        CellValue::Empty => Value::Nil,
+        CellValue::Bool(b) => Value::Boolean(*b),
+        CellValue::Integer(n) => Value::Integer(*n),
+        CellValue::Number(n) => Value::Number(*n),
+        CellValue::Date {
+            year,
+            month,
+            day,
+            time,
+        } => {
+            // the fields of os.time() / os.date("*t")
+            let date = lua.create_table()?;
+            date.set("year", *year)?;
+            date.set("month", *month)?;
+            date.set("day", *day)?;
+            if let Some((hour, min, sec)) = time {
+                date.set("hour", *hour)?;
+                date.set("min", *min)?;
+                date.set("sec", *sec)?;
+            }
+            Value::Table(date)
+        }
+        CellValue::Text(s) => Value::String(lua.create_string(s)?),
+    })
+}
+
+/// Converts a grid to rows of values, empty cells are `nil` so `width` and `height` give the size.
+pub fn grid_to_lua_table(lua: &Lua, grid: &[Vec<Cell>]) -> mlua::Result<Table> {
+    let rows = lua.create_table()?;
+    for (i, row) in grid.iter().enumerate() {
+        let row_table = lua.create_table()?;
+        for (j, cell) in row.iter().enumerate() {
+            row_table.set(j + 1, cell_value_to_lua(lua, &cell.value)?)?;
+        }
+        rows.set(i + 1, row_table)?;
+    }
+
+    rows.set("height", grid.len())?;
+    rows.set("width", grid.iter().map(|r| r.len()).max().unwrap_or(0))?;
+
+    Ok(rows)
+}
+
+fn lua_value_to_cell_value(value: Value) -> mlua::Result<CellValue> {
+    Ok(match value {
+        Value::Nil => CellValue::Empty,
+        Value::Boolean(b) => CellValue::Bool(b),
+        Value::Integer(n) => CellValue::Integer(n),
+        Value::Number(n) => CellValue::Number(n),
+        Value::String(s) => CellValue::Text(s.to_str()?.to_string()),
+        Value::Table(t) if t.contains_key("year")? => {
+            let time = match t.get::<Option<i64>>("hour")? {
+                Some(hour) => Some((
+                    hour,
+                    t.get::<Option<i64>>("min")?.unwrap_or(0),
+                    t.get::<Option<i64>>("sec")?.unwrap_or(0),
+                )),
+                None => None,
+            };
+            CellValue::Date {
+                year: t.get("year")?,
+                month: t.get::<Option<i64>>("month")?.unwrap_or(1),
+                day: t.get::<Option<i64>>("day")?.unwrap_or(1),
+                time,
+            }
+        }
+        // cells of qlp.tables
+        Value::Table(t) => CellValue::Text(lua_value_to_text(Value::Table(t))?),
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "grid cells must be strings, numbers, booleans, dates or nil".to_string(),
+            ));
+        }
+    })
+}
+
+/// Reads rows of values, `width` and `height` are used if set since rows may have holes.
+///
+/// Values equal to the cell at the same place in `source` keep its text.
+pub fn lua_table_to_grid(value: &Table, source: &[Vec<Cell>]) -> mlua::Result<Vec<Vec<Cell>>> {
+    let height = match value.get::<Option<usize>>("height")? {
+        Some(h) => h,
+        None => max_index(value)?,
+    };
+
+    let mut rows = Vec::with_capacity(height);
+    for i in 1..=height {
+        let row = match value.get::<Value>(i)? {
+            Value::Table(row) => {
+                let width = match value.get::<Option<usize>>("width")? {
+                    Some(w) => w,
+                    None => max_index(&row)?,
+                };
+                (1..=width)
+                    .map(|j| {
+                        let cell_value = lua_value_to_cell_value(row.get::<Value>(j)?)?;
+                        Ok(match source.get(i - 1).and_then(|r| r.get(j - 1)) {
+                            Some(cell) if cell.value == cell_value => cell.clone(),
+                            _ => Cell::from(cell_value),
+                        })
+                    })
+                    .collect::<mlua::Result<Vec<_>>>()?
+            }
+            Value::Nil => vec![],
+            _ => {
+                return Err(mlua::Error::RuntimeError(
+                    "grid rows must be tables".to_string(),
+                ));
+            }
+        };
+        rows.push(row);
+    }
+
+    Ok(rows)
+}
+
+fn max_index(table: &Table) -> mlua::Result<usize> {
+    let mut max = 0;
+    for pair in table.pairs::<Value, Value>() {
+        if let (Value::Integer(i), _) = pair?
+            && i > 0
+        {
+            max = max.max(i as usize);
+        }
+    }
+
+    Ok(max)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::html::{html_handle_to_string, parse_html};
+
+    use super::*;
+
+    #[test]
+    fn test_infer() {
+        assert_eq!(CellValue::Empty, CellValue::infer("  "));
+        assert_eq!(CellValue::Bool(true), CellValue::infer("TRUE"));
+        assert_eq!(CellValue::Integer(-1234), CellValue::infer("(1,234)"));
+        assert_eq!(CellValue::Integer(-5), CellValue::infer("-$5"));
+        assert_eq!(CellValue::Number(1234.5), CellValue::infer("¥1,234.50"));
+        assert_eq!(CellValue::Number(0.125), CellValue::infer("12.5%"));
+        assert_eq!(CellValue::Number(1.5e10), CellValue::infer("1.50E+10"));
+        assert_eq!(
+            CellValue::Date {
+                year: 2024,
+                month: 1,
+                day: 31,
+                time: None
+            },
+            CellValue::infer("1/31/2024")
+        );
+        assert_eq!(
+            CellValue::Date {
+                year: 2024,
+                month: 2,
+                day: 29,
+                time: Some((9, 5, 0))
+            },
+            CellValue::infer("2024-02-29 9:05")
+        );
+        for text in ["007", "1,23", "2023-02-29", "1.2.3", "-", "v1.0"] {
+            assert_eq!(CellValue::Text(text.to_string()), CellValue::infer(text));
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!("0.3", CellValue::Number(0.1 + 0.2).to_string());
+        assert_eq!("3", CellValue::Number(3.0).to_string());
+        assert_eq!("FALSE", CellValue::Bool(false).to_string());
+        assert_eq!(
+            "2024-01-31 12:00:00",
+            CellValue::infer("2024/01/31 12:00").to_string()
+        );
+        assert_eq!(
+            "2024/01/31 12:00",
+            Cell::infer("2024/01/31 12:00").to_string()
+        );
+    }
+
+    #[test]
+    fn test_grid_from_text() {
+        let grid = grid_from_text("Item\tPrice\r\n\"two\nlines\"\t1,200\r\n\t\r\n");
+        assert_eq!(3, grid.len());
+        assert_eq!(CellValue::Text("two\nlines".to_string()), grid[1][0].value);
+        assert_eq!(CellValue::Integer(1200), grid[1][1].value);
+        assert_eq!(CellValue::Empty, grid[2][1].value);
+
+        // cells are written as they were copied
+        assert_eq!(
+            "Item\tPrice\n\"two\nlines\"\t1,200\n\t\n",
+            grid_to_tsv(&grid)
+        );
+    }
+
+    #[test]
+    fn test_lua_table_to_grid() {
+        let source = grid_from_text("2024/01/31\t1,200\t3\n");
+
+        let lua = Lua::new();
+        let rows = grid_to_lua_table(&lua, &source).unwrap();
+        let row = rows.get::<Table>(1).unwrap();
+        row.set(3, 4).unwrap();
+        let grid = lua_table_to_grid(&rows, &source).unwrap();
+
+        // only the changed cell is formatted again
+        assert_eq!("2024/01/31\t1,200\t4\n", grid_to_tsv(&grid));
+    }
+
+    #[test]
+    fn test_grid_from_html() {
+        let dom = parse_html(
+            &r#"<table><tr><td colspan="2">Total</td><td align="right">
+            1,234</td></tr></table>"#
+                .to_string(),
+        );
+        let grid = grid_from_html(&dom).unwrap();
+        assert_eq!(
+            vec![vec![
+                CellValue::Text("Total".to_string()),
+                CellValue::Empty,
+                CellValue::Integer(1234)
+            ]],
+            grid.iter()
+                .map(|r| r.iter().map(|c| c.value.clone()).collect::<Vec<_>>())
+                .collect::<Vec<_>>()
+        );
+
+        assert_eq!(
+            r#"<table><tbody><tr><td>Total</td><td></td><td style="text-align: right">1,234</td></tr></tbody></table>"#,
+            html_handle_to_string(&grid_to_html_table(&grid))
+        );
+    }
+}
@@ -1,15 +1,17 @@
-use std::io::Cursor;
+use std::{io::Cursor, rc::Rc};
 
 use html5ever::{
     Attribute, parse_document,
     serialize::{HtmlSerializer, Serialize, SerializeOpts, TraversalScope},
 };
-use markup5ever::{QualName, local_name, namespace_url, ns};
+use markup5ever::{LocalName, QualName, namespace_url, ns};
 use markup5ever_rcdom::{Handle, Node, NodeData, RcDom, SerializableHandle};
 
 use mlua::{Lua, Table, Value};
 use xml5ever::tendril::{Tendril, TendrilSink};
 
+use crate::dom::LuaNode;
+
 #[derive(Debug)]
 struct Working {
     table_stack: Vec<Handle>,
@@ -43,7 +45,9 @@ fn walk(handle: &Handle, working: &mut Working) {
     if let NodeData::Element { ref name, .. } = handle.data {
         match name.local.as_ref() {
             "table" => {
+                // nested tables belong to the cell containing them
                 working.table_stack.push(handle.clone());
+                return;
             }
             "tr" => {}
             "th" => {}
@@ -62,16 +66,16 @@ fn walk(handle: &Handle, working: &mut Working) {
     }
 }
 
-fn get_rows(table: &Handle) -> Vec<Handle> {
+pub fn get_rows(table: &Handle) -> Vec<Handle> {
     let mut rows = Vec::new();
 
     let children = table.children.borrow();
     for child in children.iter() {
         if let NodeData::Element { ref name, .. } = child.data {
-            if name.local.as_ref() == "tr" {
-                rows.push(child.clone());
-            } else if name.local.as_ref() == "thead" || name.local.as_ref() == "tbody" {
-                rows.extend(get_rows(child));
+            match name.local.as_ref() {
+                "tr" => rows.push(child.clone()),
+                "thead" | "tbody" | "tfoot" => rows.extend(get_rows(child)),
+                _ => {}
             }
         }
     }
@@ -79,13 +83,13 @@ fn get_rows(table: &Handle) -> Vec<Handle> {
     rows
 }
 
-fn get_header(row: &Handle) -> Vec<Handle> {
+fn get_cells(row: &Handle) -> Vec<Handle> {
     let mut items = Vec::new();
 
     let children = row.children.borrow();
     for child in children.iter() {
         if let NodeData::Element { ref name, .. } = child.data {
-            if name.local.as_ref() == "th" {
+            if name.local.as_ref() == "td" || name.local.as_ref() == "th" {
                 items.push(child.clone());
             }
         }
@@ -94,47 +98,326 @@ fn get_header(row: &Handle) -> Vec<Handle> {
     items
 }
 
-fn get_data(row: &Handle) -> Vec<Handle> {
-    let mut items = Vec::new();
+pub fn is_element(handle: &Handle, tag: &str) -> bool {
+    match handle.data {
+        NodeData::Element { ref name, .. } => name.local.as_ref() == tag,
+        _ => false,
+    }
+}
 
-    let children = row.children.borrow();
+pub fn get_attr(handle: &Handle, key: &str) -> Option<String> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .find(|a| a.name.local.as_ref() == key)
+            .map(|a| a.value.to_string()),
+        _ => None,
+    }
+}
+
+// avoid huge grids from broken spans
+const MAX_SPAN: usize = 1000;
+
+/// A slot of the table grid
+#[derive(Debug, Clone)]
+pub struct GridCell {
+    pub handle: Handle,
+    pub is_header: bool,
+    pub colspan: usize,
+    pub rowspan: usize,
+    /// covered by a cell which starts at another slot
+    pub spanned: bool,
+}
+
+/// Expands colspan / rowspan into a rectangular grid.
+///
+/// Slots which no cell covers are `None`.
+pub fn get_grid(rows: &[Handle]) -> Vec<Vec<Option<GridCell>>> {
+    let mut grid: Vec<Vec<Option<GridCell>>> = vec![vec![]; rows.len()];
+
+    for (row_i, row) in rows.iter().enumerate() {
+        let mut column_i = 0;
+
+        for cell in get_cells(row) {
+            // skip slots taken by rowspan from the rows above
+            while grid[row_i].get(column_i).is_some_and(|e| e.is_some()) {
+                column_i += 1;
+            }
+
+            let colspan = get_attr(&cell, "colspan")
+                .and_then(|v| v.trim().parse::<usize>().ok())
+                .unwrap_or(1)
+                .clamp(1, MAX_SPAN);
+            // rowspan="0" spans to the last row
+            let rowspan =
+                match get_attr(&cell, "rowspan").and_then(|v| v.trim().parse::<usize>().ok()) {
+                    Some(0) => rows.len() - row_i,
+                    Some(n) => n.min(rows.len() - row_i),
+                    None => 1,
+                }
+                .clamp(1, MAX_SPAN);
+
+            for r in row_i..(row_i + rowspan) {
+                for c in column_i..(column_i + colspan) {
+                    if grid[r].len() <= c {
+                        grid[r].resize(c + 1, None);
+                    }
+
+                    grid[r][c] = Some(GridCell {
+                        handle: cell.clone(),
+                        is_header: is_element(&cell, "th"),
+                        colspan,
+                        rowspan,
+                        spanned: r != row_i || c != column_i,
+                    });
+                }
+            }
+
+            column_i += colspan;
+        }
+    }
+
+    let width = grid.iter().map(|r| r.len()).max().unwrap_or(0);
+    for row in grid.iter_mut() {
+        row.resize(width, None);
+    }
+
+    grid
+}
+
+/// Returns the column names from the header row.
+///
+/// The header row is the last row in `<thead>`, or the first row if all of its cells are `<th>`.
+pub fn get_headers(rows: &[Handle], grid: &[Vec<Option<GridCell>>]) -> Option<Vec<String>> {
+    let in_thead = |row: &Handle| get_parent(row).is_some_and(|p| is_element(&p, "thead"));
+
+    let header_i = match rows.iter().rposition(in_thead) {
+        Some(i) => i,
+        None => {
+            let first = grid.first()?;
+            if first.is_empty()
+                || !first
+                    .iter()
+                    .all(|c| c.as_ref().is_some_and(|c| c.is_header))
+            {
+                return None;
+            }
+            0
+        }
+    };
+
+    Some(
+        grid[header_i]
+            .iter()
+            .map(|c| match c {
+                Some(c) => get_text(&c.handle).trim().to_string(),
+                None => String::new(),
+            })
+            .collect(),
+    )
+}
+
+/// A link in a cell
+#[derive(Debug, Clone, PartialEq)]
+struct Link {
+    href: String,
+    text: String,
+    title: Option<String>,
+}
+
+/// An image in a cell
+#[derive(Debug, Clone, PartialEq)]
+struct Image {
+    src: String,
+    alt: Option<String>,
+}
+
+/// Inline formatting of a piece of text
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Format {
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub strike: bool,
+}
+
+/// A piece of text with the same formatting
+#[derive(Debug, Clone, PartialEq)]
+struct Run {
+    text: String,
+    format: Format,
+}
+
+/// Calls `f` with the descendant elements of `handle` in document order.
+///
+/// Tables nested in `handle` are skipped, they are parsed on their own.
+fn for_each_element<F: FnMut(&Handle)>(handle: &Handle, f: &mut F) {
+    let children = handle.children.borrow();
     for child in children.iter() {
-        if let NodeData::Element { ref name, .. } = child.data {
-            if name.local.as_ref() == "td" {
-                items.push(child.clone());
+        if let NodeData::Element { .. } = child.data {
+            if is_element(child, "table") {
+                continue;
             }
+
+            f(child);
         }
+
+        for_each_element(child, f);
     }
+}
 
-    items
+fn get_links(handle: &Handle) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    for_each_element(handle, &mut |e| {
+        if !is_element(e, "a") {
+            return;
+        }
+
+        if let Some(href) = get_attr(e, "href") {
+            links.push(Link {
+                href,
+                text: get_text(e),
+                title: get_attr(e, "title"),
+            });
+        }
+    });
+
+    links
+}
+
+fn get_images(handle: &Handle) -> Vec<Image> {
+    let mut images = Vec::new();
+
+    for_each_element(handle, &mut |e| {
+        if !is_element(e, "img") {
+            return;
+        }
+
+        if let Some(src) = get_attr(e, "src") {
+            images.push(Image {
+                src,
+                alt: get_attr(e, "alt"),
+            });
+        }
+    });
+
+    images
 }
 
 fn get_anchor_href(handle: &Handle) -> Option<String> {
-    let children = handle.children.borrow();
+    get_links(handle).into_iter().next().map(|l| l.href)
+}
 
-    for child in children.iter() {
-        if let NodeData::Element {
-            ref name,
-            ref attrs,
-            ..
-        } = child.data
+/// Returns the value of the CSS property `key` in the inline style of `handle`.
+pub fn get_style(handle: &Handle, key: &str) -> Option<String> {
+    let style = get_attr(handle, "style")?;
+
+    style.split(';').find_map(|decl| {
+        let (k, v) = decl.split_once(':')?;
+        if k.trim().eq_ignore_ascii_case(key) {
+            Some(v.trim().to_ascii_lowercase())
+        } else {
+            None
+        }
+    })
+}
+
+/// Applies the formatting of the element `handle` to `format`.
+pub fn apply_format(handle: &Handle, mut format: Format) -> Format {
+    if let NodeData::Element { ref name, .. } = handle.data {
+        match name.local.as_ref() {
+            "b" | "strong" => format.bold = true,
+            "i" | "em" => format.italic = true,
+            "code" | "kbd" | "samp" | "tt" => format.code = true,
+            "s" | "strike" | "del" => format.strike = true,
+            _ => {}
+        }
+    }
+
+    // spreadsheets format cells with inline styles
+    if let Some(weight) = get_style(handle, "font-weight") {
+        match weight.as_str() {
+            "bold" | "bolder" => format.bold = true,
+            "normal" | "lighter" => format.bold = false,
+            w => {
+                if let Ok(w) = w.parse::<u32>() {
+                    format.bold = w >= 600;
+                }
+            }
+        }
+    }
+    if let Some(style) = get_style(handle, "font-style") {
+        format.italic = style == "italic" || style == "oblique";
+    }
+    if let Some(decoration) =
+        get_style(handle, "text-decoration").or_else(|| get_style(handle, "text-decoration-line"))
+    {
+        if decoration.contains("line-through") {
+            format.strike = true;
+        } else if decoration == "none" {
+            format.strike = false;
+        }
+    }
+    if let Some(family) = get_style(handle, "font-family") {
+        if family.contains("monospace") || family.contains("courier") || family.contains("consolas")
         {
-            if name.local.as_ref() == "a" {
-                for attr in attrs.borrow().iter() {
-                    if attr.name.local.as_ref() == "href" {
-                        return Some(attr.value.to_string());
+            format.code = true;
+        }
+    }
+
+    format
+}
+
+fn get_runs(handle: &Handle) -> Vec<Run> {
+    fn collect(handle: &Handle, format: Format, runs: &mut Vec<Run>) {
+        let children = handle.children.borrow();
+        for child in children.iter() {
+            match child.data {
+                NodeData::Text { ref contents } => {
+                    let text = contents.borrow();
+                    if text.is_empty() {
+                        continue;
+                    }
+
+                    // join with the previous run of the same formatting
+                    match runs.last_mut() {
+                        Some(last) if last.format == format => last.text.push_str(text.as_ref()),
+                        _ => runs.push(Run {
+                            text: text.to_string(),
+                            format,
+                        }),
                     }
                 }
-            } else {
-                return get_anchor_href(child);
+                NodeData::Element { .. } => {
+                    if is_element(child, "table") {
+                        continue;
+                    }
+
+                    collect(child, apply_format(child, format), runs);
+                }
+                _ => {}
             }
         }
     }
 
-    None
+    let mut runs = Vec::new();
+    collect(handle, apply_format(handle, Format::default()), &mut runs);
+
+    runs
+}
+
+pub fn get_parent(handle: &Handle) -> Option<Handle> {
+    // Cell<Option<Weak>> can only be read by taking it out
+    let weak = handle.parent.take();
+    let parent = weak.as_ref().and_then(|w| w.upgrade());
+    handle.parent.set(weak);
+
+    parent
 }
 
-fn get_text(handle: &Handle) -> String {
+pub fn get_text(handle: &Handle) -> String {
     let mut text = String::new();
 
     let children = handle.children.borrow();
@@ -149,6 +432,203 @@ fn get_text(handle: &Handle) -> String {
     text
 }
 
+/// Returns the tables under `handle` in document order, not including the tables nested in them.
+pub fn get_tables(handle: &Handle) -> Vec<Handle> {
+    let mut working = Working::default();
+
+    let children = handle.children.borrow();
+    for child in children.iter() {
+        walk(child, &mut working);
+    }
+
+    working.table_stack
+}
+
+fn get_caption(table: &Handle) -> Option<Handle> {
+    table
+        .children
+        .borrow()
+        .iter()
+        .find(|c| is_element(c, "caption"))
+        .cloned()
+}
+
+pub fn get_attrs(handle: &Handle) -> Vec<(String, String)> {
+    match handle.data {
+        NodeData::Element { ref attrs, .. } => attrs
+            .borrow()
+            .iter()
+            .map(|a| (a.name.local.to_string(), a.value.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn cell_to_lua_table(lua: &mlua::Lua, cell: &Option<GridCell>) -> mlua::Table {
+    let data_table = lua.create_table().unwrap();
+    match cell {
+        Some(cell) => {
+            data_table.set("text", get_text(&cell.handle)).unwrap();
+            if let Some(href) = get_anchor_href(&cell.handle) {
+                data_table.set("href", href).unwrap();
+            }
+            data_table.set("is_header", cell.is_header).unwrap();
+            data_table.set("colspan", cell.colspan).unwrap();
+            data_table.set("rowspan", cell.rowspan).unwrap();
+            data_table.set("spanned", cell.spanned).unwrap();
+            data_table
+                .set("html", html_children_to_string(&cell.handle))
+                .unwrap();
+
+            let links = lua.create_table().unwrap();
+            for (i, link) in get_links(&cell.handle).into_iter().enumerate() {
+                let link_table = lua.create_table().unwrap();
+                link_table.set("href", link.href).unwrap();
+                link_table.set("text", link.text).unwrap();
+                link_table.set("title", link.title).unwrap();
+                links.set(i + 1, link_table).unwrap();
+            }
+            data_table.set("links", links).unwrap();
+
+            let formats = lua.create_table().unwrap();
+            for (i, run) in get_runs(&cell.handle).into_iter().enumerate() {
+                let run_table = lua.create_table().unwrap();
+                run_table.set("text", run.text).unwrap();
+                run_table.set("bold", run.format.bold).unwrap();
+                run_table.set("italic", run.format.italic).unwrap();
+                run_table.set("code", run.format.code).unwrap();
+                run_table.set("strike", run.format.strike).unwrap();
+                formats.set(i + 1, run_table).unwrap();
+            }
+            data_table.set("formats", formats).unwrap();
+
+            let images = lua.create_table().unwrap();
+            for (i, image) in get_images(&cell.handle).into_iter().enumerate() {
+                let image_table = lua.create_table().unwrap();
+                image_table.set("src", image.src).unwrap();
+                image_table.set("alt", image.alt).unwrap();
+                images.set(i + 1, image_table).unwrap();
+            }
+            data_table.set("images", images).unwrap();
+
+            let nested = get_tables(&cell.handle);
+            if !nested.is_empty() {
+                let tables = lua.create_table().unwrap();
+                for (i, nested) in nested.iter().enumerate() {
+                    tables.set(i + 1, table_to_lua_table(lua, nested)).unwrap();
+                }
+                data_table.set("tables", tables).unwrap();
+            }
+        }
+        None => {
+            // missing cell of a ragged row
+            data_table.set("text", "").unwrap();
+            data_table.set("is_header", false).unwrap();
+            data_table.set("colspan", 1).unwrap();
+            data_table.set("rowspan", 1).unwrap();
+            data_table.set("spanned", false).unwrap();
+            data_table.set("html", "").unwrap();
+            data_table
+                .set("links", lua.create_table().unwrap())
+                .unwrap();
+            data_table
+                .set("formats", lua.create_table().unwrap())
+                .unwrap();
+            data_table
+                .set("images", lua.create_table().unwrap())
+                .unwrap();
+        }
+    }
+
+    data_table
+}
+
+fn table_to_lua_table(lua: &mlua::Lua, table_handle: &Handle) -> mlua::Table {
+    // Table
+    //   +-- rows: (table)
+    //   |    +-- row_n : (table)
+    //   |    |            +-- column_n: (table)
+    //   |    |            |              +-- (if anchor) href: url
+    //   |    |            |              +-- text: text
+    //   |    |            |              +-- is_header: th or not
+    //   |    |            |              +-- colspan / rowspan: span of the cell
+    //   |    |            |              +-- spanned: covered by a span from another slot
+    //   |    |            |              +-- html: inner HTML
+    //   |    |            |              +-- links: list of {href, text, title}
+    //   |    |            |              +-- formats: list of {text, bold, italic, code, strike}
+    //   |    |            |              +-- images: list of {src, alt}
+    //   |    |            |              `-- (if nested) tables: same as this table
+    //   |    |            +-- :
+    //   |    |            +-- (if headers) column name: same as column_n
+    //   |    |            `-- :
+    //   |    +-- :
+    //   |    `-- :
+    //   +-- (if header row) headers: column names
+    //   +-- (if caption) caption: text
+    //   `-- attrs: attributes of <table>
+
+    let rows = get_rows(table_handle);
+    let grid = get_grid(&rows);
+    let headers = get_headers(&rows, &grid);
+
+    let rows_table = lua.create_table().unwrap();
+    for (row_i, row) in grid.iter().enumerate() {
+        let row_table = lua.create_table().unwrap();
+        let is_header_row = row.iter().all(|c| c.as_ref().is_some_and(|c| c.is_header));
+
+        for (column_n, cell) in row.iter().enumerate() {
+            let data_table = cell_to_lua_table(lua, cell);
+
+            // address columns by header name
+            if let Some(name) = headers.as_ref().and_then(|h| h.get(column_n)) {
+                if !is_header_row
+                    && !name.is_empty()
+                    && !row_table.contains_key(name.as_str()).unwrap()
+                {
+                    row_table.set(name.as_str(), data_table.clone()).unwrap();
+                }
+            }
+
+            // lua to start arrays with index 1
+            row_table.set(column_n + 1, data_table).unwrap();
+        }
+
+        // lua to start arrays with index 1
+        rows_table.set(row_i + 1, row_table).unwrap();
+    }
+
+    let table = lua.create_table().unwrap();
+    table.set("rows", rows_table).unwrap();
+    if let Some(headers) = headers {
+        table.set("headers", headers).unwrap();
+    }
+    if let Some(caption) = get_caption(table_handle) {
+        table.set("caption", get_text(&caption).trim()).unwrap();
+    }
+    table
+        .set(
+            "attrs",
+            lua.create_table_from(get_attrs(table_handle)).unwrap(),
+        )
+        .unwrap();
+
+    table
+}
+
+/// Returns every top-level table of the document in document order.
+pub fn rc_dom_to_lua_tables(lua: &mlua::Lua, dom: &RcDom) -> mlua::Table {
+    let tables = lua.create_table().unwrap();
+    for (i, table_handle) in get_tables(&dom.document).iter().enumerate() {
+        // lua to start arrays with index 1
+        tables
+            .set(i + 1, table_to_lua_table(lua, table_handle))
+            .unwrap();
+    }
+
+    tables
+}
+
+/// Returns the rows of every top-level table, concatenated in document order.
 pub fn rc_dom_to_lua_table(lua: &mlua::Lua, dom: RcDom) -> mlua::Table {
     let mut working = Working::default();
     walk(&dom.document, &mut working);
@@ -169,90 +649,52 @@ pub fn rc_dom_to_lua_table(lua: &mlua::Lua, dom: RcDom) -> mlua::Table {
         table.set(1, row_table).unwrap();
     }
 
-    while working.table_stack.len() > 0 {
-        let table_handle = working.table_stack.pop().unwrap();
-        for (row_i, row) in get_rows(&table_handle).iter().enumerate() {
-            let row_table = lua.create_table().unwrap();
-
-            for (column_n, cell) in get_data(&row).iter().enumerate() {
-                // Table
-                //   +-- row_n : (table)
-                //   |            +-- column_n: (table)
-                //   |            |              +-- (if anchor) href: url
-                //   |            |              `-- text: text
-                //   |            +-- :
-                //   |            +-- :
-                //   |            `-- :
-                //   +-- :
-                //   +-- :
-                //   `-- :
-
-                let data_table = lua.create_table().unwrap();
-                data_table.set("text", get_text(cell)).unwrap();
-                if let Some(href) = get_anchor_href(cell) {
-                    data_table.set("href", href).unwrap();
-                }
-
-                // lua to start arrays with index 1
-                row_table.set(column_n + 1, data_table).unwrap();
-            }
+    let mut row_n = 0;
+    for table_handle in working.table_stack.iter() {
+        let converted = table_to_lua_table(lua, table_handle);
 
-            // lua to start arrays with index 1
-            table.set(row_i + 1, row_table).unwrap();
+        let rows = converted.get::<mlua::Table>("rows").unwrap();
+        for row in rows.sequence_values::<mlua::Table>() {
+            row_n += 1;
+            table.set(row_n, row.unwrap()).unwrap();
         }
-    }
 
-    table
-}
-
-fn print_table(table: &mlua::Table, indent: u32) {
-    for pair in table.pairs::<mlua::Value, mlua::Value>() {
-        let (key, value) = pair.unwrap();
-        print!("{:indent$}", " ", indent = (indent * 2 + 1) as usize);
-        if value.is_table() {
-            println!("{:?}:", key);
-            print_table(value.as_table().unwrap(), indent + 1);
-        } else {
-            println!("{:?}: {:?}", key, value);
+        // the first table with a header row names the columns
+        if !table.contains_key("headers").unwrap() {
+            if let Some(headers) = converted.get::<Option<mlua::Table>>("headers").unwrap() {
+                table.set("headers", headers).unwrap();
+            }
         }
     }
-}
 
-fn create_html() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("html")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
-}
-
-fn create_body() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("body")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+    table
 }
 
-fn create_meta<T>(key: T, value: T) -> Handle
+/// Creates an element of any tag with attributes.
+pub fn create_element<T>(tag: T, attrs: Vec<(String, String)>) -> Handle
 where
     T: ToString,
 {
     Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("meta")),
-        attrs: vec![Attribute {
-            name: QualName::new(None, ns!(), key.to_string().into()),
-            value: value.to_string().into(),
-        }]
-        .into(),
+        name: QualName::new(
+            None,
+            ns!(html),
+            LocalName::from(tag.to_string().to_ascii_lowercase()),
+        ),
+        attrs: attrs
+            .into_iter()
+            .map(|(key, value)| Attribute {
+                name: QualName::new(None, ns!(), LocalName::from(key)),
+                value: value.into(),
+            })
+            .collect::<Vec<_>>()
+            .into(),
         template_contents: None.into(),
         mathml_annotation_xml_integration_point: false,
     })
 }
 
-fn create_comment<T>(text: T) -> Handle
+pub fn create_comment<T>(text: T) -> Handle
 where
     T: ToString,
 {
@@ -261,201 +703,541 @@ where
     })
 }
 
-fn create_table() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("table")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
+pub fn create_text<T>(text: T) -> Handle
+where
+    T: ToString,
+{
+    Node::new(NodeData::Text {
+        contents: Tendril::from(text.to_string()).into(),
     })
 }
 
-fn create_tr() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("tr")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+/// Appends `child` to `parent`, so that `child` knows its parent.
+///
+/// A child that already has a parent is moved, as `appendChild` does in the DOM.
+pub fn append_child(parent: &Handle, child: Handle) {
+    remove_from_parent(&child);
+    child.parent.set(Some(Rc::downgrade(parent)));
+    parent.children.borrow_mut().push(child);
 }
 
-fn create_td() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("td")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+fn remove_from_parent(handle: &Handle) {
+    if let Some(parent) = get_parent(handle) {
+        parent
+            .children
+            .borrow_mut()
+            .retain(|child| !Rc::ptr_eq(child, handle));
+        handle.parent.set(None);
+    }
 }
 
-fn create_a(href: String) -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("a")),
-        attrs: vec![Attribute {
-            name: QualName::new(None, ns!(), local_name!("href")),
-            value: href.into(),
-        }]
-        .into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
-}
+/// Converts a Lua attribute table to attributes, in name order.
+///
+/// `true` becomes an attribute without value, `false` and `nil` are left out.
+pub fn lua_value_to_attrs(value: &Value) -> mlua::Result<Vec<(String, String)>> {
+    let table = match value {
+        Value::Nil => return Ok(vec![]),
+        Value::Table(t) => t,
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "attributes must be a table".to_string(),
+            ));
+        }
+    };
+
+    let mut attrs = Vec::new();
+    for pair in table.pairs::<String, Value>() {
+        let (key, value) = pair?;
+        match value {
+            Value::Boolean(true) => attrs.push((key, String::new())),
+            Value::Boolean(false) | Value::Nil => {}
+            Value::String(s) => attrs.push((key, s.to_str()?.to_string())),
+            Value::Integer(n) => attrs.push((key, n.to_string())),
+            Value::Number(n) => attrs.push((key, n.to_string())),
+            _ => {
+                return Err(mlua::Error::RuntimeError(format!(
+                    "attribute {} must be a string, number or boolean",
+                    key
+                )));
+            }
+        }
+    }
 
-fn create_text<T>(text: T) -> Handle
-where
-    T: ToString,
-{
-    Node::new(NodeData::Text {
-        contents: Tendril::from(text.to_string()).into(),
-    })
+    // pairs() has no order
+    attrs.sort();
+
+    Ok(attrs)
 }
 
-fn create_ul() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("ul")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+/// Adds `style` to the style attribute in `attrs`.
+fn push_style(attrs: &mut Vec<(String, String)>, style: &str) {
+    let style = style.trim().trim_end_matches(';');
+    if style.is_empty() {
+        return;
+    }
+
+    match attrs.iter_mut().find(|(k, _)| k == "style") {
+        Some((_, value)) => {
+            let current = value.trim().trim_end_matches(';').to_string();
+            *value = if current.is_empty() {
+                format!("{};", style)
+            } else {
+                format!("{};{};", current, style)
+            };
+        }
+        None => attrs.push(("style".to_string(), format!("{};", style))),
+    }
 }
 
-fn create_ol() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("ol")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+/// Returns `attrs` and `style` of a table, row or cell as attributes.
+fn get_lua_attrs(value: &Table) -> mlua::Result<Vec<(String, String)>> {
+    let mut attrs = lua_value_to_attrs(&value.get::<Value>("attrs")?)?;
+
+    if let Some(style) = value.get::<Option<String>>("style")? {
+        push_style(&mut attrs, &style);
+    }
+
+    Ok(attrs)
 }
 
-fn create_li() -> Handle {
-    Node::new(NodeData::Element {
-        name: QualName::new(None, ns!(html), local_name!("li")),
-        attrs: vec![].into(),
-        template_contents: None.into(),
-        mathml_annotation_xml_integration_point: false,
-    })
+fn get_span(value: &Table, key: &str) -> usize {
+    value
+        .get::<Option<usize>>(key)
+        .ok()
+        .flatten()
+        .unwrap_or(1)
+        .clamp(1, MAX_SPAN)
 }
 
-pub fn lua_table_to_html_table(_: &Lua, value: &Table) -> Handle {
-    let table = create_table();
+/// Creates a `<td>` or `<th>` from a cell for `qlp.result`.
+///
+/// Returns `None` for slots covered by the span of another cell, e.g. cells of `qlp.tables`.
+fn lua_value_to_cell(
+    value: &Value,
+    is_header: bool,
+    align: Option<&str>,
+) -> mlua::Result<Option<(Handle, usize, usize)>> {
+    let (text, cell_table) = match value {
+        Value::Table(t) => (t.get::<Option<String>>("text").ok().flatten(), Some(t)),
+        Value::String(s) => (Some(s.to_string_lossy()), None),
+        Value::Integer(n) => (Some(n.to_string()), None),
+        Value::Number(n) => (Some(n.to_string()), None),
+        Value::Boolean(b) => (Some(b.to_string()), None),
+        _ => (None, None),
+    };
+
+    let mut attrs = vec![];
+    let mut is_header = is_header;
+    let mut align = align.map(|a| a.to_string());
+    let mut href = None;
+    let (mut colspan, mut rowspan) = (1, 1);
+
+    if let Some(t) = cell_table {
+        if t.get::<Option<bool>>("spanned")
+            .ok()
+            .flatten()
+            .unwrap_or(false)
+        {
+            return Ok(None);
+        }
 
-    for row_kv in value.pairs::<Value, Value>() {
-        let (row_key, row_value) = row_kv.unwrap();
-        let row_table = row_value.as_table().unwrap();
+        attrs = get_lua_attrs(t)?;
+        is_header = t
+            .get::<Option<bool>>("is_header")
+            .ok()
+            .flatten()
+            .unwrap_or(is_header);
+        align = t.get::<Option<String>>("align").ok().flatten().or(align);
+        href = t.get::<Option<String>>("href").ok().flatten();
+        colspan = get_span(t, "colspan");
+        rowspan = get_span(t, "rowspan");
+    }
 
-        let tr = create_tr();
+    if colspan > 1 {
+        attrs.push(("colspan".to_string(), colspan.to_string()));
+    }
+    if rowspan > 1 {
+        attrs.push(("rowspan".to_string(), rowspan.to_string()));
+    }
+    if let Some(align) = align {
+        // spreadsheets read the style rather than the align attribute
+        push_style(&mut attrs, &format!("text-align:{}", align));
+    }
 
-        for cell_kv in row_table.pairs::<Value, Value>() {
-            let (column_key, column_value) = cell_kv.unwrap();
-            println!("{:?} {:?}", column_key, column_value);
-            let column_table = column_value.as_table().unwrap();
+    let cell = create_element(if is_header { "th" } else { "td" }, attrs);
+    let text = text.unwrap_or_default();
 
-            let td = create_td();
+    match href {
+        Some(href) => {
+            let a = create_element("a", vec![("href".to_string(), href)]);
+            append_child(&a, create_text(text));
+            append_child(&cell, a);
+        }
+        None => {
+            if !text.is_empty() {
+                append_child(&cell, create_text(text));
+            }
+        }
+    }
 
-            let text = column_table.get::<String>("text").unwrap();
-            let href = column_table.get::<String>("href");
+    Ok(Some((cell, colspan, rowspan)))
+}
 
-            if href.is_ok() {
-                let a = create_a(href.unwrap());
-                a.children.borrow_mut().push(create_text(text));
+/// Creates a `<tr>`, `occupied` holds the rows left for each column spanned from above.
+fn lua_value_to_row(
+    value: &Value,
+    is_header: bool,
+    aligns: &[String],
+    occupied: &mut Vec<usize>,
+) -> mlua::Result<Handle> {
+    let tr = create_element("tr", vec![]);
+    let cells = match value {
+        Value::Table(t) => t
+            .sequence_values::<Value>()
+            .filter_map(|v| v.ok())
+            .collect(),
+        // a row of a single value
+        v => vec![v.clone()],
+    };
+
+    let mut column = 0;
+    for cell in cells.iter() {
+        while occupied.get(column).is_some_and(|n| *n > 0) {
+            column += 1;
+        }
 
-                td.children.borrow_mut().push(a);
-            } else {
-                td.children.borrow_mut().push(create_text(text));
-            }
+        let align = aligns
+            .get(column)
+            .map(|a| a.as_str())
+            .filter(|a| !a.is_empty());
+        let Some((cell, colspan, rowspan)) = lua_value_to_cell(cell, is_header, align)? else {
+            continue;
+        };
 
-            tr.children.borrow_mut().push(td);
+        if occupied.len() < column + colspan {
+            occupied.resize(column + colspan, 0);
+        }
+        for n in occupied[column..column + colspan].iter_mut() {
+            *n = rowspan;
         }
 
-        table.children.borrow_mut().push(tr);
+        append_child(&tr, cell);
+        column += colspan;
     }
 
-    return table;
-}
+    // the row is done, spans from above go down one row
+    for n in occupied.iter_mut() {
+        *n = n.saturating_sub(1);
+    }
 
-fn lua_table_to_ul(lua: &Lua, value: &Table) -> Handle {
-    let ul = create_ul();
+    Ok(tr)
+}
 
-    if value.contains_key("text").unwrap() {
-        let li = create_li();
-        if value.contains_key("href").unwrap() {
-            let a = create_a(value.get::<String>("href").unwrap());
-            let text = create_text(value.get::<String>("text").unwrap());
-            a.children.borrow_mut().push(text);
-            li.children.borrow_mut().push(a);
-        } else {
-            let text = create_text(value.get::<String>("text").unwrap());
-            li.children.borrow_mut().push(text);
+/// Returns whether every cell of the row is a header cell.
+fn is_lua_header_row(value: &Value) -> bool {
+    match value {
+        Value::Table(t) => {
+            let cells = t
+                .sequence_values::<Value>()
+                .filter_map(|v| v.ok())
+                .collect::<Vec<_>>();
+            !cells.is_empty()
+                && cells.iter().all(|c| match c {
+                    Value::Table(c) => c
+                        .get::<Option<bool>>("is_header")
+                        .ok()
+                        .flatten()
+                        .unwrap_or(false),
+                    _ => false,
+                })
         }
-        ul.children.borrow_mut().push(li);
+        _ => false,
     }
+}
 
-    for cell_kv in value.pairs::<Value, Value>() {
-        let (column_key, column_value) = cell_kv.unwrap();
+/// Converts a table for `qlp.result` to `<table>`.
+///
+/// ```lua
+/// {
+///     header = { "Name", "Count" },
+///     align = { "left", "right" },
+///     { "foo", 1 },
+///     { { text = "bar", href = "https://..." }, { text = 2, style = "color:red" } },
+/// }
+/// ```
+pub fn lua_table_to_html_table(_: &Lua, value: &Table) -> mlua::Result<Handle> {
+    let table = create_element("table", get_lua_attrs(value)?);
+
+    // { nil, "right" } leaves a hole, read by index
+    let mut aligns = vec![];
+    if let Some(align) = value.get::<Option<Table>>("align").ok().flatten() {
+        for (i, a) in align.pairs::<usize, Value>().filter_map(|p| p.ok()) {
+            if let (true, Value::String(a)) = (i > 0, a) {
+                if aligns.len() < i {
+                    aligns.resize(i, String::new());
+                }
+                aligns[i - 1] = a.to_string_lossy();
+            }
+        }
+    }
 
-        if !column_value.is_table() {
-            continue;
+    // rows, or the array part like qlp.parsed
+    let rows = match value.get::<Option<Table>>("rows").ok().flatten() {
+        Some(rows) => rows,
+        None => value.clone(),
+    };
+    let rows = rows
+        .sequence_values::<Value>()
+        .filter_map(|v| v.ok())
+        .collect::<Vec<_>>();
+
+    // header rows, given explicitly or leading rows of header cells as in qlp.tables
+    let mut header_rows = vec![];
+    if let Some(header) = value.get::<Option<Value>>("header").ok().flatten() {
+        header_rows.push((header, true));
+    }
+    let leading = rows.iter().take_while(|r| is_lua_header_row(r)).count();
+    header_rows.extend(rows[..leading].iter().map(|r| (r.clone(), false)));
+
+    let mut occupied = vec![];
+
+    if !header_rows.is_empty() {
+        let thead = create_element("thead", vec![]);
+        for (row, is_header) in header_rows.iter() {
+            append_child(
+                &thead,
+                lua_value_to_row(row, *is_header, &aligns, &mut occupied)?,
+            );
         }
+        append_child(&table, thead);
+    }
+
+    let tbody = create_element("tbody", vec![]);
+    for row in rows[leading..].iter() {
+        append_child(
+            &tbody,
+            lua_value_to_row(row, false, &aligns, &mut occupied)?,
+        );
+    }
+    append_child(&table, tbody);
+
+    Ok(table)
+}
+
+/// A list for `qlp.result`
+///
+/// ```lua
+/// { type = "ul", items = { "text", { text = "link", href = "https://...", items = { ... } } } }
+/// ```
+struct List {
+    ordered: bool,
+    start: i64,
+    items: Vec<ListItem>,
+}
 
-        let column_table = column_value.as_table().unwrap();
+struct ListItem {
+    text: Option<String>,
+    href: Option<String>,
+    node: Option<Handle>,
+    children: Option<List>,
+}
+
+/// Returns whether `value` has the shape of a list, `{type = "ul" | "ol", ...}`.
+pub fn is_lua_list(value: &Table) -> bool {
+    matches!(
+        value
+            .get::<Option<String>>("type")
+            .ok()
+            .flatten()
+            .as_deref(),
+        Some("ul") | Some("ol")
+    )
+}
 
-        let created_ul = lua_table_to_ul(lua, column_table);
-        let children = ul.children.borrow().clone();
-        let found_ul = children.iter().find(|e| {
-            match e.data {
-                NodeData::Element { ref name, .. } => {
-                    if name.local.as_ref() == "ul" {
-                        return true;
+fn lua_table_to_list(value: &Table, ordered: bool) -> List {
+    let ordered = match value
+        .get::<Option<String>>("type")
+        .ok()
+        .flatten()
+        .as_deref()
+    {
+        Some("ol") => true,
+        Some("ul") => false,
+        _ => ordered,
+    };
+    let start = value
+        .get::<Option<i64>>("start")
+        .ok()
+        .flatten()
+        .unwrap_or(1);
+
+    // items, or the array part like { text = "parent", { text = "child" } }
+    let items = match value.get::<Option<Table>>("items").ok().flatten() {
+        Some(items) => items,
+        None => value.clone(),
+    };
+
+    let mut list = List {
+        ordered,
+        start,
+        items: vec![],
+    };
+
+    for item in items.sequence_values::<Value>() {
+        let item = match item {
+            Ok(item) => item,
+            Err(_) => continue,
+        };
+
+        match item {
+            Value::String(s) => list.items.push(ListItem::text(s.to_string_lossy())),
+            Value::Integer(n) => list.items.push(ListItem::text(n.to_string())),
+            Value::Number(n) => list.items.push(ListItem::text(n.to_string())),
+            Value::UserData(ud) => {
+                if let Ok(node) = ud.borrow::<LuaNode>() {
+                    list.items.push(ListItem {
+                        text: None,
+                        href: None,
+                        node: Some(node.0.clone()),
+                        children: None,
+                    });
+                }
+            }
+            Value::Table(t) => {
+                let text = t.get::<Option<String>>("text").ok().flatten();
+                let href = t.get::<Option<String>>("href").ok().flatten();
+                let children = lua_table_to_list(&t, ordered);
+                let children = (!children.items.is_empty()).then_some(children);
+
+                match list.items.last_mut() {
+                    // a list without text nests in the previous item
+                    Some(last) if text.is_none() && href.is_none() && last.children.is_none() => {
+                        last.children = children;
                     }
+                    _ => list.items.push(ListItem {
+                        text,
+                        href,
+                        node: None,
+                        children,
+                    }),
                 }
-                _ => {
-                    return false;
+            }
+            _ => {}
+        }
+    }
+
+    list
+}
+
+impl ListItem {
+    fn text(text: String) -> Self {
+        ListItem {
+            text: Some(text),
+            href: None,
+            node: None,
+            children: None,
+        }
+    }
+}
+
+fn list_to_html(list: &List) -> Handle {
+    let mut attrs = vec![];
+    if list.ordered && list.start != 1 {
+        attrs.push(("start".to_string(), list.start.to_string()));
+    }
+    let element = create_element(if list.ordered { "ol" } else { "ul" }, attrs);
+
+    for item in list.items.iter() {
+        let li = create_element("li", vec![]);
+
+        let text = item.text.clone().unwrap_or_default();
+        match (&item.href, &item.node) {
+            (Some(href), _) => {
+                let a = create_element("a", vec![("href".to_string(), href.clone())]);
+                let text = if text.is_empty() { href.clone() } else { text };
+                append_child(&a, create_text(text));
+                append_child(&li, a);
+            }
+            (None, Some(node)) => append_child(&li, node.clone()),
+            (None, None) => {
+                if !text.is_empty() {
+                    append_child(&li, create_text(text));
                 }
             }
+        }
+
+        if let Some(children) = &item.children {
+            append_child(&li, list_to_html(children));
+        }
 
-            return false;
-        });
+        append_child(&element, li);
+    }
 
-        if found_ul.is_some() {
-            let mut ul_children = found_ul.unwrap().children.borrow_mut();
-            ul_children.append(&mut created_ul.children.borrow_mut());
+    element
+}
+
+fn list_to_plain_text(list: &List, indent: usize, lines: &mut Vec<String>) {
+    for (i, item) in list.items.iter().enumerate() {
+        let marker = if list.ordered {
+            format!("{}. ", list.start + i as i64)
         } else {
-            ul.children.borrow_mut().push(created_ul);
+            "- ".to_string()
+        };
+
+        let text = match (&item.text, &item.href, &item.node) {
+            (Some(text), Some(href), _) if !text.is_empty() && text != href => {
+                format!("{} ({})", text, href)
+            }
+            (_, Some(href), _) => href.clone(),
+            (_, None, Some(node)) => LuaNode(node.clone()).text(),
+            (Some(text), None, None) => text.clone(),
+            (None, None, None) => String::new(),
+        };
+
+        lines.push(format!("{}{}{}", " ".repeat(indent), marker, text));
+
+        if let Some(children) = &item.children {
+            list_to_plain_text(children, indent + marker.len(), lines);
         }
     }
+}
 
-    return ul;
+/// Converts a list for `qlp.result` to nested `<ul>` / `<ol>`.
+pub fn lua_table_to_html_list(_: &Lua, value: &Table) -> Handle {
+    list_to_html(&lua_table_to_list(value, false))
 }
 
-pub fn lua_table_to_html_list(lua: &Lua, value: &Table) -> Handle {
-    print_table(value, 0);
-    println!("--------------------------------------------------------------------------------");
+/// Converts a list for `qlp.result` to indented plain text.
+pub fn lua_table_to_plain_list(value: &Table) -> String {
+    let mut lines = vec![];
+    list_to_plain_text(&lua_table_to_list(value, false), 0, &mut lines);
 
-    let mut result: Option<Handle> = None;
+    lines.join("\n")
+}
 
-    for row_kv in value.pairs::<Value, Value>() {
-        let (row_key, row_value) = row_kv.unwrap();
-        if result.is_some() {
-            let ul = Some(lua_table_to_ul(lua, row_value.as_table().unwrap()));
-            result
-                .clone()
-                .unwrap()
-                .children
-                .borrow_mut()
-                .append(&mut ul.unwrap().children.borrow_mut());
-        } else {
-            result = Some(lua_table_to_ul(lua, row_value.as_table().unwrap()));
-        }
+/// Returns the contents of the body for a document, a document cannot be serialized by itself.
+fn body_contents(handle: Handle) -> Vec<Handle> {
+    if !matches!(handle.data, NodeData::Document) {
+        return vec![handle];
     }
 
-    return result.unwrap();
+    let mut body = None;
+    for_each_element(&handle, &mut |e| {
+        if body.is_none() && is_element(e, "body") {
+            body = Some(e.clone());
+        }
+    });
+
+    match body {
+        Some(body) => body.children.borrow().clone(),
+        None => handle.children.borrow().clone(),
+    }
 }
 
 pub fn create_html_for_clipboard(contents: Vec<Handle>) -> Handle {
-    let html = create_html();
-    let body = create_body();
-    let meta_charset = create_meta("charset", "utf-8");
+    let html = create_element("html", vec![]);
+    let body = create_element("body", vec![]);
+    let meta_charset = create_element("meta", vec![("charset".to_string(), "utf-8".to_string())]);
 
     let comment_start_fragment = create_comment("StartFragment");
     let comment_end_fragment = create_comment("EndFragment");
@@ -464,7 +1246,9 @@ pub fn create_html_for_clipboard(contents: Vec<Handle>) -> Handle {
     let mut content = vec![];
     content.push(comment_start_fragment);
     content.push(meta_charset);
-    content.extend(contents);
+    for handle in contents {
+        content.extend(body_contents(handle));
+    }
     content.push(comment_end_fragment);
 
     body.children.borrow_mut().append(&mut content);
@@ -474,6 +1258,14 @@ pub fn create_html_for_clipboard(contents: Vec<Handle>) -> Handle {
 }
 
 pub fn html_handle_to_string(handle: &Handle) -> String {
+    serialize_handle(handle, TraversalScope::IncludeNode)
+}
+
+pub fn html_children_to_string(handle: &Handle) -> String {
+    serialize_handle(handle, TraversalScope::ChildrenOnly(None))
+}
+
+fn serialize_handle(handle: &Handle, scope: TraversalScope) -> String {
     let mut buf = vec![];
     let mut serializer = HtmlSerializer::new(
         &mut buf,
@@ -483,9 +1275,7 @@ pub fn html_handle_to_string(handle: &Handle) -> String {
         },
     );
     let serializable = SerializableHandle::from(handle.clone());
-    serializable
-        .serialize(&mut serializer, TraversalScope::IncludeNode)
-        .unwrap();
+    serializable.serialize(&mut serializer, scope).unwrap();
     String::from_utf8(buf).unwrap()
 }
 
@@ -496,6 +1286,19 @@ mod tests {
 
     use super::*;
 
+    fn print_table(table: &mlua::Table, indent: u32) {
+        for pair in table.pairs::<mlua::Value, mlua::Value>() {
+            let (key, value) = pair.unwrap();
+            print!("{:indent$}", " ", indent = (indent * 2 + 1) as usize);
+            if value.is_table() {
+                println!("{:?}:", key);
+                print_table(value.as_table().unwrap(), indent + 1);
+            } else {
+                println!("{:?}: {:?}", key, value);
+            }
+        }
+    }
+
     #[test]
     fn test_parse_html() {
         let html = r#"<html>
@@ -626,6 +1429,149 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_html_spans() {
+        let html = r#"<html><body><table>
+<thead><tr><th>Name</th><th colspan="2">Contact</th></tr></thead>
+<tbody>
+<tr><td rowspan="2">foo</td><td>mail</td><td>foo@example.com</td></tr>
+<tr><td>tel</td></tr>
+</tbody>
+</table></body></html>"#;
+
+        let dom = parse_html(&html.to_string());
+        let lua = mlua::Lua::new();
+        let table = rc_dom_to_lua_table(&lua, dom);
+
+        // rows are rectangular
+        assert_eq!(3, table.len().unwrap());
+        for row_i in 1..=3 {
+            let row = table.get::<Table>(row_i).unwrap();
+            assert_eq!(3, row.len().unwrap());
+        }
+
+        let headers = table.get::<Vec<String>>("headers").unwrap();
+        assert_eq!(vec!["Name", "Contact", "Contact"], headers);
+
+        // header row
+        let row = table.get::<Table>(1).unwrap();
+        let cell = row.get::<Table>(1).unwrap();
+        assert!(cell.get::<bool>("is_header").unwrap());
+        let cell = row.get::<Table>(3).unwrap();
+        assert_eq!("Contact", cell.get::<String>("text").unwrap());
+        assert_eq!(2, cell.get::<usize>("colspan").unwrap());
+        assert!(cell.get::<bool>("spanned").unwrap());
+
+        // data row is addressed by the header name, first column wins
+        let row = table.get::<Table>(2).unwrap();
+        let cell = row.get::<Table>("Name").unwrap();
+        assert_eq!("foo", cell.get::<String>("text").unwrap());
+        assert!(!cell.get::<bool>("is_header").unwrap());
+        let cell = row.get::<Table>("Contact").unwrap();
+        assert_eq!("mail", cell.get::<String>("text").unwrap());
+
+        // rowspan fills the slot below
+        let row = table.get::<Table>(3).unwrap();
+        let cell = row.get::<Table>(1).unwrap();
+        assert_eq!("foo", cell.get::<String>("text").unwrap());
+        assert!(cell.get::<bool>("spanned").unwrap());
+        let cell = row.get::<Table>(2).unwrap();
+        assert_eq!("tel", cell.get::<String>("text").unwrap());
+
+        // missing cell
+        let cell = row.get::<Table>(3).unwrap();
+        assert_eq!("", cell.get::<String>("text").unwrap());
+        assert!(!cell.get::<bool>("spanned").unwrap());
+    }
+
+    #[test]
+    fn test_parse_html_tables() {
+        let html = r#"<html><body>
+<table id="first"><caption> Users </caption><tr><td>foo</td><td><table><tr><td>nested</td></tr></table></td></tr></table>
+<p>text</p>
+<table id="second"><tr><th>Name</th></tr><tr><td>bar</td></tr></table>
+</body></html>"#;
+
+        let dom = parse_html(&html.to_string());
+        let lua = mlua::Lua::new();
+        lua.globals()
+            .set("tables", rc_dom_to_lua_tables(&lua, &dom))
+            .unwrap();
+        lua.globals()
+            .set("parsed", rc_dom_to_lua_table(&lua, dom))
+            .unwrap();
+
+        lua.load(
+            r#"
+            assert(#tables == 2)
+            assert(tables[1].attrs.id == "first")
+            assert(tables[1].caption == "Users")
+            assert(tables[1].headers == nil)
+            assert(#tables[1].rows == 1)
+            assert(tables[1].rows[1][1].text == "foo")
+
+            local nested = tables[1].rows[1][2].tables
+            assert(#nested == 1)
+            assert(nested[1].rows[1][1].text == "nested")
+            assert(tables[1].rows[1][1].tables == nil)
+
+            assert(tables[2].attrs.id == "second")
+            assert(tables[2].caption == nil)
+            assert(tables[2].headers[1] == "Name")
+            assert(tables[2].rows[2].Name.text == "bar")
+
+            -- rows of every table in document order
+            assert(#parsed == 3)
+            assert(parsed[1][1].text == "foo")
+            assert(parsed[2][1].text == "Name")
+            assert(parsed[3][1].text == "bar")
+            assert(parsed.headers[1] == "Name")
+            "#,
+        )
+        .exec()
+        .unwrap();
+    }
+
+    #[test]
+    fn test_parse_html_cell_formats() {
+        let html = r#"<html><body><table><tr><td>See <a href="https://example.com/1" title="first">#1</a> and <b><a href="https://example.com/2">#2</a></b><img src="icon.png" alt="icon"></td></tr></table></body></html>"#;
+
+        let dom = parse_html(&html.to_string());
+        let lua = mlua::Lua::new();
+        lua.globals()
+            .set("tables", rc_dom_to_lua_tables(&lua, &dom))
+            .unwrap();
+
+        lua.load(
+            r##"
+            local cell = tables[1].rows[1][1]
+            assert(cell.text == "See #1 and #2")
+            assert(cell.href == "https://example.com/1")
+
+            assert(#cell.links == 2)
+            assert(cell.links[1].text == "#1")
+            assert(cell.links[1].title == "first")
+            assert(cell.links[2].href == "https://example.com/2")
+            assert(cell.links[2].title == nil)
+
+            assert(#cell.formats == 2)
+            assert(cell.formats[1].text == "See #1 and ")
+            assert(not cell.formats[1].bold)
+            assert(cell.formats[2].text == "#2")
+            assert(cell.formats[2].bold)
+            assert(not cell.formats[2].italic)
+
+            assert(#cell.images == 1)
+            assert(cell.images[1].src == "icon.png")
+            assert(cell.images[1].alt == "icon")
+
+            assert(string.find(cell.html, '^See <a href="https://example.com/1"'))
+            "##,
+        )
+        .exec()
+        .unwrap();
+    }
+
     #[test]
     fn test_to_html_table() {
         let lua = mlua::Lua::new();
@@ -655,14 +1601,76 @@ mod tests {
         table.set(2, row2).unwrap();
         table.set(3, row3).unwrap();
 
-        let actual_table = lua_table_to_html_table(&lua, &table);
-        let actual_html = create_html_for_clipboard(vec![actual_table]);
+        let actual_table = lua_table_to_html_table(&lua, &table).unwrap();
+        let actual = html_handle_to_string(&actual_table);
+
+        let expected = r#"<table><tbody><tr><td>aa</td></tr><tr><td>bb</td></tr><tr><td><a href="https://example.com/">cc</a></td></tr></tbody></table>"#;
+        assert_eq!(expected, actual);
+    }
+
+    #[test]
+    fn test_to_html_table_2() {
+        let lua = mlua::Lua::new();
+        let table = lua
+            .load(
+                r#"
+                {
+                    header = { "Name", { text = "Count", colspan = 2 } },
+                    align = { nil, "right" },
+                    style = "border-collapse:collapse",
+                    { "foo", 1, { text = "x", style = "color:red", attrs = { class = "note" } } },
+                    { { text = "bar", rowspan = 2 }, 2.5, true },
+                    { 3, { text = "", spanned = true }, "y" },
+                }
+                "#,
+            )
+            .eval::<Table>()
+            .unwrap();
+
+        let actual = html_handle_to_string(&lua_table_to_html_table(&lua, &table).unwrap());
+        let expected = concat!(
+            r#"<table style="border-collapse:collapse;">"#,
+            r#"<thead><tr><th>Name</th><th colspan="2" style="text-align:right;">Count</th></tr></thead>"#,
+            r#"<tbody><tr><td>foo</td><td style="text-align:right;">1</td><td class="note" style="color:red;">x</td></tr>"#,
+            r#"<tr><td rowspan="2">bar</td><td style="text-align:right;">2.5</td><td>true</td></tr>"#,
+            r#"<tr><td style="text-align:right;">3</td><td>y</td></tr></tbody>"#,
+            r#"</table>"#,
+        );
+        assert_eq!(expected, actual);
+
+        let table = lua
+            .load(r#"{ { { text = "x", attrs = "bold" } } }"#)
+            .eval::<Table>()
+            .unwrap();
+        assert!(lua_table_to_html_table(&lua, &table).is_err());
+    }
+
+    #[test]
+    fn test_to_html_table_from_parsed() {
+        let html = r#"<table><tr><th>a</th><th>b</th></tr><tr><td colspan="2">c</td></tr></table>"#;
+        let dom = parse_html(&html.to_string());
+
+        let lua = mlua::Lua::new();
+        let tables = rc_dom_to_lua_tables(&lua, &dom);
+        let table = tables.get::<Table>(1).unwrap();
+
+        let actual = html_handle_to_string(&lua_table_to_html_table(&lua, &table).unwrap());
+        let expected = r#"<table><thead><tr><th>a</th><th>b</th></tr></thead><tbody><tr><td colspan="2">c</td></tr></tbody></table>"#;
+        assert_eq!(expected, actual);
+    }
 
-        println!("{:?}", actual_html);
+    #[test]
+    fn test_append_child_moves_node() {
+        let first = create_element("p", vec![]);
+        let second = create_element("div", vec![]);
+        let text = create_text("moved");
 
-        println!("{}", html_handle_to_string(&actual_html));
+        append_child(&first, text.clone());
+        append_child(&second, text.clone());
 
-        assert!(false)
+        assert_eq!("<p></p>", html_handle_to_string(&first));
+        assert_eq!("<div>moved</div>", html_handle_to_string(&second));
+        assert!(Rc::ptr_eq(&second, &get_parent(&text).unwrap()));
     }
 
     #[test]
@@ -709,13 +1717,43 @@ mod tests {
         table.set(1, row1).unwrap();
         table.set(2, row2).unwrap();
 
-        // let actual_html = merge_ul(lua_table_to_html_list(&lua, &table));
+        table.set("type", "ul").unwrap();
+
         let actual_html = lua_table_to_html_list(&lua, &table);
         let actual = html_handle_to_string(&actual_html);
-        println!("{:?}", actual);
 
-        let expected = "<ul><li>foo</li><ul><li>aa</li><li>cc</li></ul><li>bar</li><ul><li>bb</li><li>dd</li></ul></ul>";
+        let expected = "<ul><li>foo<ul><li>aa</li><li>cc</li></ul></li><li>bar<ul><li>bb</li><li>dd</li></ul></li></ul>";
 
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn test_list_3() {
+        let lua = mlua::Lua::new();
+        let table = lua
+            .load(
+                r#"
+                {
+                    type = "ol",
+                    start = 9,
+                    items = {
+                        "nine",
+                        { text = "ten", href = "https://example.com/" },
+                        { type = "ol", items = { "a", 1 } },
+                    },
+                }
+                "#,
+            )
+            .eval::<Table>()
+            .unwrap();
+
+        assert!(is_lua_list(&table));
+
+        let actual = html_handle_to_string(&lua_table_to_html_list(&lua, &table));
+        let expected = r#"<ol start="9"><li>nine</li><li><a href="https://example.com/">ten</a><ol><li>a</li><li>1</li></ol></li></ol>"#;
+        assert_eq!(expected, actual);
+
+        let expected = "9. nine\n10. ten (https://example.com/)\n    1. a\n    2. 1";
+        assert_eq!(expected, lua_table_to_plain_list(&table));
+    }
 }
@@ -1,26 +1,59 @@
+mod blocks;
 mod builtin;
 mod builtins;
+mod cf_html;
 mod clip;
+mod dom;
 mod error;
+mod file_clipboard;
 #[cfg(target_os = "windows")]
 mod global_memory;
+mod grid;
 mod html;
+mod markdown;
+#[cfg(test)]
+mod memory_clipboard;
+mod plain_text;
+mod selector;
 mod utils;
+#[cfg(target_os = "linux")]
+mod wayland_clipboard;
 #[cfg(target_os = "windows")]
 mod win_clipboard;
+#[cfg(target_os = "linux")]
+mod x11_clipboard;
+mod xml;
 
 use std::{fs::read_to_string, io::Read, path::PathBuf};
 
-use clap::Parser;
+use cf_html::CfHtml;
+use clap::{Parser, ValueEnum};
 use clip::{Clip, Clipboard, ClipboardFormat};
+use dom::LuaNode;
+use file_clipboard::FileClipboard;
+use grid::{
+    grid_from_html, grid_from_text, grid_to_html_table, grid_to_lua_table, grid_to_tsv,
+    lua_table_to_grid,
+};
 use html::{
-    create_html_for_clipboard, html_handle_to_string, lua_table_to_html_table, parse_html,
-    rc_dom_to_lua_table,
+    create_html_for_clipboard, html_handle_to_string, is_lua_list, lua_table_to_html_list,
+    lua_table_to_html_table, lua_table_to_plain_list, parse_html, rc_dom_to_lua_table,
+    rc_dom_to_lua_tables,
 };
+use markdown::{html_to_markdown, markdown_to_html};
 use mlua::Value;
+use plain_text::html_to_plain_text;
 
 use crate::error::Error;
 
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Backend {
+    /// OS clipboard
+    System,
+    /// directory with one file per format (see --clipboard-dir)
+    File,
+}
+
 #[derive(Debug, Parser, Clone)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -28,19 +61,41 @@ struct Args {
     file: Option<PathBuf>,
     #[arg(long, default_value_t = false)]
     to_stdout: bool,
+    #[arg(long, value_enum, default_value_t = Backend::System)]
+    clipboard: Backend,
+    #[arg(
+        long,
+        default_value = "clipboard",
+        help = "directory for --clipboard file"
+    )]
+    clipboard_dir: PathBuf,
 }
 
-fn set_clipboard_or_stdout(
+/// Puts every format in `data` on the clipboard, or prints the plain text (or the first one).
+fn set_clipboard_list_or_stdout<C: Clip>(
     to_stdout: bool,
-    clipboard: &mut Clipboard,
-    data: &ClipboardFormat,
+    clipboard: &mut C,
+    data: &[ClipboardFormat],
 ) -> Result<(), Error> {
     if to_stdout {
-        println!("{}", data.to_string());
+        let text = data
+            .iter()
+            .find(|d| matches!(d, ClipboardFormat::Text(_)))
+            .or(data.first());
+        if let Some(text) = text {
+            println!("{}", text.to_string());
+        }
         return Ok(());
     }
 
-    clipboard.set_data(data)
+    clipboard.set_data_list(data)
+}
+
+/// Wraps an HTML fragment into a document for the clipboard.
+fn html_for_clipboard(html: &str) -> String {
+    let parsed = parse_html(&html.to_string());
+    let html_handle = create_html_for_clipboard(vec![parsed.document]);
+    html_handle_to_string(&html_handle)
 }
 
 fn main() {
@@ -65,46 +120,104 @@ fn main() {
         }
     };
 
-    let mut clip = Clipboard::new();
+    match args.clipboard {
+        Backend::System => run(&mut Clipboard::new(), script, args.to_stdout),
+        Backend::File => run(
+            &mut FileClipboard::with_dir(&args.clipboard_dir),
+            script,
+            args.to_stdout,
+        ),
+    }
+}
 
+fn run<C: Clip>(clip: &mut C, script: String, to_stdout: bool) {
     let lua = mlua::Lua::new();
     let _ = builtin::init(&lua).unwrap();
 
-    let format = clip.determine_format().unwrap();
+    // an empty or unsupported clipboard is treated as empty text
+    let format = clip.determine_format().unwrap_or_default();
 
-    match format {
+    // the grid as copied, cells the script leaves alone are written back as they were
+    let source_grid = match format {
         ClipboardFormat::Html(_) => {
             let table = lua.create_table().unwrap();
 
-            let html = clip.get_data(&format).unwrap().to_string();
-            table.set("raw", html.clone()).unwrap();
+            let raw = clip.get_data(&format).unwrap().to_string();
+            table.set("raw", raw.clone()).unwrap();
 
             let text = clip
                 .get_data(&ClipboardFormat::Text("".to_string()))
-                .unwrap()
-                .to_string();
+                .map(|d| d.to_string())
+                .unwrap_or_default();
             table.set("text", text.clone()).unwrap();
 
-            let html = Clipboard::get_html(&html);
+            // CF_HTML on Windows, plain HTML elsewhere
+            let cf_html = CfHtml::decode(&raw).unwrap_or_else(|e| {
+                eprintln!("{}", e);
+                CfHtml::new(&raw)
+            });
+            let html = cf_html.html().to_string();
             table.set("html", html.clone()).unwrap();
 
+            table.set("fragment", cf_html.fragment()).unwrap();
+            // the selection equals the fragment when the header has no StartSelection
+            table
+                .set(
+                    "selection",
+                    cf_html.selection().unwrap_or(cf_html.fragment()),
+                )
+                .unwrap();
+            table.set("source_url", cf_html.source_url()).unwrap();
+            table
+                .set("markdown", html_to_markdown(cf_html.fragment()))
+                .unwrap();
+
             let dom = parse_html(&html);
+            table.set("dom", LuaNode(dom.document.clone())).unwrap();
+
+            let document = LuaNode(dom.document.clone());
+            let select = lua
+                .create_function(move |_, css: String| document.select(&css))
+                .unwrap();
+            table.set("select", select).unwrap();
+
+            table
+                .set("tables", rc_dom_to_lua_tables(&lua, &dom))
+                .unwrap();
+
+            // spreadsheets put the range both as a table and as TSV
+            let grid = grid_from_html(&dom).unwrap_or_else(|| grid_from_text(&text));
+            table
+                .set("grid", grid_to_lua_table(&lua, &grid).unwrap())
+                .unwrap();
+
             let parsed_table = rc_dom_to_lua_table(&lua, dom);
             table.set("parsed", parsed_table).unwrap();
 
             lua.globals().set("qlp", table).unwrap();
+            grid
         }
-        ClipboardFormat::Text(_) => {
+        // RTF is only written, never picked as the source
+        ClipboardFormat::Text(_) | ClipboardFormat::Rtf(_) => {
             let table = lua.create_table().unwrap();
 
-            let text = clip.get_data(&format).unwrap().to_string();
+            let text = clip
+                .get_data(&format)
+                .map(|d| d.to_string())
+                .unwrap_or_default();
             table.set("raw", text.clone()).unwrap();
 
             table.set("text", text.clone()).unwrap();
 
+            let grid = grid_from_text(&text);
+            table
+                .set("grid", grid_to_lua_table(&lua, &grid).unwrap())
+                .unwrap();
+
             lua.globals().set("qlp", table).unwrap();
+            grid
         }
-    }
+    };
 
     // execute lua script
     match lua.load(script).exec() {
@@ -114,47 +227,39 @@ fn main() {
         }
     }
 
-    // set clipboard
+    // collect every result first, so that they reach the clipboard in one transaction
     let current_table = lua.globals().get::<mlua::Table>("qlp").unwrap();
+    let mut outputs: Vec<ClipboardFormat> = vec![];
 
     match current_table.get::<Value>("result") {
-        Ok(value) => match value {
-            Value::String(s) => set_clipboard_or_stdout(
-                args.to_stdout,
-                &mut clip,
-                &ClipboardFormat::Text(s.to_string_lossy()),
-            )
-            .unwrap(),
-            Value::Nil => {
-                // NOP
+        Ok(value) => match result_to_formats(&lua, value) {
+            Ok(formats) => merge_formats(&mut outputs, formats),
+            Err(e) => eprintln!("{}", e),
+        },
+        Err(_) => {}
+    }
+
+    if let Ok(Value::Table(t)) = current_table.get::<Value>("result_grid") {
+        match lua_table_to_grid(&t, &source_grid) {
+            Ok(grid) => {
+                let html_handle = create_html_for_clipboard(vec![grid_to_html_table(&grid)]);
+                merge_formats(
+                    &mut outputs,
+                    vec![
+                        ClipboardFormat::Html(html_handle_to_string(&html_handle)),
+                        ClipboardFormat::Text(grid_to_tsv(&grid)),
+                    ],
+                );
             }
-            Value::Boolean(b) => set_clipboard_or_stdout(
-                args.to_stdout,
-                &mut clip,
-                &ClipboardFormat::Text(b.to_string()),
-            )
-            .unwrap(),
-            Value::Integer(n) => set_clipboard_or_stdout(
-                args.to_stdout,
-                &mut clip,
-                &ClipboardFormat::Text(n.to_string()),
-            )
-            .unwrap(),
-            Value::Number(n) => set_clipboard_or_stdout(
-                args.to_stdout,
-                &mut clip,
-                &ClipboardFormat::Text(n.to_string()),
-            )
-            .unwrap(),
-            Value::Table(t) => {
-                // TODO: list
-                let handles = vec![lua_table_to_html_table(&lua, &t)];
-                // let handles = lua_table_to_html_list(&lua, &t);
-                let html_handle = create_html_for_clipboard(handles);
-                let html = html_handle_to_string(&html_handle);
-
-                set_clipboard_or_stdout(args.to_stdout, &mut clip, &ClipboardFormat::Html(html))
-                    .unwrap();
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    match current_table.get::<Value>("result_html_raw") {
+        Ok(value) => match value {
+            Value::String(s) => {
+                let html_str = html_for_clipboard(&s.to_string_lossy());
+                merge_formats(&mut outputs, vec![ClipboardFormat::Html(html_str)]);
             }
             _ => {
                 // NOP
@@ -163,13 +268,19 @@ fn main() {
         Err(_) => {}
     }
 
-    match current_table.get::<Value>("result_html_raw") {
+    match current_table.get::<Value>("result_markdown") {
         Ok(value) => match value {
             Value::String(s) => {
-                let parsed = parse_html(&s.to_string_lossy());
-                let html_handle = create_html_for_clipboard(vec![parsed.document]);
-                let html_str = html_handle_to_string(&html_handle);
-                clip.set_data(&ClipboardFormat::Html(html_str)).unwrap();
+                // rich text for editors, the source for plain text fields
+                let markdown = s.to_string_lossy();
+                let html_str = html_for_clipboard(&markdown_to_html(&markdown));
+                merge_formats(
+                    &mut outputs,
+                    vec![
+                        ClipboardFormat::Html(html_str),
+                        ClipboardFormat::Text(markdown),
+                    ],
+                );
             }
             _ => {
                 // NOP
@@ -177,4 +288,366 @@ fn main() {
         },
         Err(_) => {}
     }
+
+    // plain text for the targets that cannot take HTML
+    let html = outputs.iter().find_map(|o| match o {
+        ClipboardFormat::Html(html) => Some(html.clone()),
+        _ => None,
+    });
+    if let Some(html) = html
+        && !outputs
+            .iter()
+            .any(|o| matches!(o, ClipboardFormat::Text(_)))
+    {
+        outputs.push(ClipboardFormat::Text(html_to_plain_text(&html)));
+    }
+
+    if !outputs.is_empty() {
+        set_clipboard_list_or_stdout(to_stdout, clip, &outputs).unwrap();
+    }
+}
+
+/// Converts `qlp.result` to the formats to put on the clipboard.
+fn result_to_formats(lua: &mlua::Lua, value: Value) -> mlua::Result<Vec<ClipboardFormat>> {
+    Ok(match value {
+        Value::String(s) => vec![ClipboardFormat::Text(s.to_string_lossy())],
+        Value::Boolean(b) => vec![ClipboardFormat::Text(b.to_string())],
+        Value::Integer(n) => vec![ClipboardFormat::Text(n.to_string())],
+        Value::Number(n) => vec![ClipboardFormat::Text(n.to_string())],
+        Value::Table(t) if is_lua_formats(&t) => lua_table_to_formats(&t)?,
+        Value::Table(t) if is_lua_list(&t) => {
+            let handles = vec![lua_table_to_html_list(lua, &t)];
+            let html_handle = create_html_for_clipboard(handles);
+
+            vec![
+                ClipboardFormat::Html(html_handle_to_string(&html_handle)),
+                ClipboardFormat::Text(lua_table_to_plain_list(&t)),
+            ]
+        }
+        Value::Table(t) => {
+            let handles = vec![lua_table_to_html_table(lua, &t)?];
+            let html_handle = create_html_for_clipboard(handles);
+
+            vec![ClipboardFormat::Html(html_handle_to_string(&html_handle))]
+        }
+        Value::UserData(ud) => match ud.borrow::<LuaNode>() {
+            Ok(node) => vec![ClipboardFormat::Html(node_to_html(&node))],
+            Err(_) => vec![],
+        },
+        _ => vec![],
+    })
+}
+
+fn node_to_html(node: &LuaNode) -> String {
+    let html_handle = create_html_for_clipboard(vec![node.0.clone()]);
+    html_handle_to_string(&html_handle)
+}
+
+/// `{text = ..., html = ..., rtf = ...}` without rows.
+fn is_lua_formats(table: &mlua::Table) -> bool {
+    table.raw_len() == 0
+        && ["text", "html", "rtf"]
+            .iter()
+            .any(|k| table.contains_key(*k).unwrap_or(false))
+}
+
+fn lua_table_to_formats(table: &mlua::Table) -> mlua::Result<Vec<ClipboardFormat>> {
+    let mut formats = vec![];
+
+    match table.get::<Value>("html")? {
+        Value::Nil => {}
+        Value::String(s) => formats.push(ClipboardFormat::Html(html_for_clipboard(
+            &s.to_string_lossy(),
+        ))),
+        Value::UserData(ud) => formats.push(ClipboardFormat::Html(node_to_html(
+            &ud.borrow::<LuaNode>()?,
+        ))),
+        _ => {
+            return Err(mlua::Error::RuntimeError(
+                "html must be a string or a node".to_string(),
+            ));
+        }
+    }
+
+    if let Some(rtf) = table.get::<Option<String>>("rtf")? {
+        formats.push(ClipboardFormat::Rtf(rtf));
+    }
+
+    if let Some(text) = table.get::<Option<String>>("text")? {
+        formats.push(ClipboardFormat::Text(text));
+    }
+
+    Ok(formats)
+}
+
+/// Adds `formats` to `outputs`, replacing the ones of the same format.
+fn merge_formats(outputs: &mut Vec<ClipboardFormat>, formats: Vec<ClipboardFormat>) {
+    for format in formats {
+        match outputs.iter_mut().find(|e| e.is_same_format(&format)) {
+            Some(e) => *e = format,
+            None => outputs.push(format),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory_clipboard::MemoryClipboard;
+
+    #[test]
+    fn test_run_with_memory_clipboard() {
+        let mut clip = MemoryClipboard::with_data(vec![ClipboardFormat::Text("hello".to_string())]);
+
+        run(
+            &mut clip,
+            "qlp.result = string.upper(qlp.text)".to_string(),
+            false,
+        );
+
+        assert_eq!(
+            "HELLO",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_cf_html() {
+        let mut cf_html = CfHtml::new(
+            "<html><body><!--StartFragment--><b>Example</b><!--EndFragment--></body></html>",
+        );
+        cf_html.set_source_url(Some("https://example.com/".to_string()));
+
+        let mut clip = MemoryClipboard::with_data(vec![
+            ClipboardFormat::Html(cf_html.encode()),
+            ClipboardFormat::Text("Example".to_string()),
+        ]);
+
+        run(
+            &mut clip,
+            r#"qlp.result = qlp.fragment .. "|" .. qlp.selection .. "|" .. qlp.source_url"#
+                .to_string(),
+            false,
+        );
+
+        assert_eq!(
+            "<b>Example</b>|<b>Example</b>|https://example.com/",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_markdown() {
+        let mut clip = MemoryClipboard::with_data(vec![ClipboardFormat::Text("done".to_string())]);
+
+        run(
+            &mut clip,
+            r#"qlp.result_markdown = "**" .. qlp.text .. "**""#.to_string(),
+            false,
+        );
+
+        assert_eq!(2, clip.formats().len());
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains("<p><strong>done</strong></p>")
+        );
+        assert_eq!(
+            "**done**",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_node() {
+        let mut clip = MemoryClipboard::new();
+
+        run(
+            &mut clip,
+            r#"qlp.result = h("a", { href = "https://example.com/" }, "example")"#.to_string(),
+            false,
+        );
+
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains(r#"<a href="https://example.com/">example</a><!--EndFragment-->"#)
+        );
+        assert_eq!(
+            "example (https://example.com/)",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_table() {
+        let mut clip = MemoryClipboard::new();
+
+        run(
+            &mut clip,
+            r#"qlp.result = { header = { "Name", "Count" }, { "foo", 1 }, { { text = "bar", colspan = 2 } } }"#
+                .to_string(),
+            false,
+        );
+
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains("<th>Name</th>")
+        );
+        assert_eq!(
+            "Name\tCount\nfoo\t1\nbar\t",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_list() {
+        let mut clip = MemoryClipboard::new();
+
+        run(
+            &mut clip,
+            r#"qlp.result = { type = "ul", items = { "foo", { text = "bar", items = { "baz" } } } }"#
+                .to_string(),
+            false,
+        );
+
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains("<ul><li>foo</li><li>bar<ul><li>baz</li></ul></li></ul>")
+        );
+        assert_eq!(
+            "- foo\n- bar\n  - baz",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_formats() {
+        let mut clip = MemoryClipboard::new();
+
+        run(
+            &mut clip,
+            r#"qlp.result = { text = "foo", html = "<b>foo</b>", rtf = [[{\rtf1 {\b foo}}]] }"#
+                .to_string(),
+            false,
+        );
+
+        assert_eq!(3, clip.formats().len());
+        assert_eq!(
+            "foo",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains("<b>foo</b>")
+        );
+        assert_eq!(
+            r"{\rtf1 {\b foo}}",
+            clip.get_data(&ClipboardFormat::Rtf("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
+
+    #[test]
+    fn test_run_with_result_html_raw_and_result() {
+        let mut clip = MemoryClipboard::new();
+
+        run(
+            &mut clip,
+            r#"qlp.result = "foo"; qlp.result_html_raw = "<i>foo</i>""#.to_string(),
+            false,
+        );
+
+        // both survive instead of the HTML replacing the text
+        assert_eq!(
+            "foo",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains("<i>foo</i>")
+        );
+    }
+
+    #[test]
+    fn test_run_with_grid() {
+        let mut clip = MemoryClipboard::with_data(vec![ClipboardFormat::Text(
+            "Item\tPrice\tDate\r\nfoo\t1,200\t2024/01/31\r\nbar\t\tTRUE\r\n".to_string(),
+        )]);
+
+        run(
+            &mut clip,
+            r#"
+            local grid = qlp.grid
+            assert(grid.width == 3 and grid.height == 3)
+            assert(grid[2][2] == 1200 and grid[3][2] == nil and grid[3][3] == true)
+            assert(grid[2][3].year == 2024 and grid[2][3].month == 1 and grid[2][3].day == 31)
+
+            local total = 0
+            for i = 2, grid.height do
+                total = total + (grid[i][2] or 0)
+            end
+            grid[3][2] = total * 1.5
+            grid[4] = { "total", total }
+            grid.height = 4
+            qlp.result_grid = grid
+            "#
+            .to_string(),
+            false,
+        );
+
+        assert_eq!(
+            "Item\tPrice\tDate\nfoo\t1,200\t2024/01/31\nbar\t1800\tTRUE\ntotal\t1200\t\n",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .unwrap()
+                .to_string()
+                .contains(r#"<td style="text-align: right">1800</td>"#)
+        );
+    }
+
+    #[test]
+    fn test_run_with_empty_clipboard() {
+        let mut clip = MemoryClipboard::new();
+
+        run(&mut clip, "qlp.result = #qlp.text".to_string(), false);
+
+        assert_eq!(
+            "0",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+    }
 }
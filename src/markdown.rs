@@ -0,0 +1,593 @@
+//! Conversion between HTML and Markdown (GitHub Flavored Markdown)
+//!
+//! # Example
+//! ```lua
+//! qlp.result = html_to_markdown(qlp.fragment)
+//! qlp.result_markdown = "**done**: " .. qlp.text
+//! ```
+
+use std::ops::Range;
+
+use markup5ever_rcdom::Handle;
+use pulldown_cmark::{CowStr, Event, LinkType, Options, Parser, Tag, TagEnd, TextMergeStream};
+
+use crate::{
+    blocks::{
+        Emitter, clean_lines, collapse_whitespace, is_block, render_blocks, render_inline_children,
+        tag_of,
+    },
+    html::{
+        Format, GridCell, apply_format, get_attr, get_grid, get_headers, get_parent, get_rows,
+        get_style, get_text, is_element, parse_html,
+    },
+};
+
+/// Converts HTML to Markdown.
+pub fn html_to_markdown(html: &str) -> String {
+    let dom = parse_html(&html.to_string());
+
+    let mut blocks = Vec::new();
+    render_blocks(&Markdown, &dom.document, Format::default(), &mut blocks);
+
+    blocks.join("\n\n")
+}
+
+/// Converts Markdown to HTML.
+///
+/// CommonMark with the GFM extensions: tables, task lists, strikethrough and autolinks.
+pub fn markdown_to_html(markdown: &str) -> String {
+    let options = Options::ENABLE_TABLES
+        | Options::ENABLE_TASKLISTS
+        | Options::ENABLE_STRIKETHROUGH
+        | Options::ENABLE_GFM;
+    let parser = TextMergeStream::new(Parser::new_ext(markdown, options));
+
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, autolink(parser));
+
+    html
+}
+
+/// Links bare URLs in text, pulldown-cmark only links `<url>`.
+fn autolink<'a, I: Iterator<Item = Event<'a>>>(events: I) -> impl Iterator<Item = Event<'a>> {
+    // inside links, images or code blocks
+    let mut depth = 0;
+
+    events.flat_map(move |event| {
+        match &event {
+            Event::Start(Tag::Link { .. } | Tag::Image { .. } | Tag::CodeBlock(_)) => depth += 1,
+            Event::End(TagEnd::Link | TagEnd::Image | TagEnd::CodeBlock) => depth -= 1,
+            Event::Text(text) if depth == 0 => return link_urls(text),
+            _ => {}
+        }
+
+        vec![event]
+    })
+}
+
+fn link_urls<'a>(text: &str) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+    let mut last = 0;
+
+    for url in find_urls(text) {
+        if last < url.start {
+            events.push(Event::Text(CowStr::from(text[last..url.start].to_string())));
+        }
+
+        let url_text = &text[url.clone()];
+        let dest_url = if url_text.starts_with("www.") {
+            format!("http://{}", url_text)
+        } else {
+            url_text.to_string()
+        };
+
+        events.push(Event::Start(Tag::Link {
+            link_type: LinkType::Autolink,
+            dest_url: CowStr::from(dest_url),
+            title: CowStr::from(""),
+            id: CowStr::from(""),
+        }));
+        events.push(Event::Text(CowStr::from(url_text.to_string())));
+        events.push(Event::End(TagEnd::Link));
+
+        last = url.end;
+    }
+
+    if last < text.len() {
+        events.push(Event::Text(CowStr::from(text[last..].to_string())));
+    }
+
+    events
+}
+
+/// Returns the ranges of `http://`, `https://` and `www.` URLs in `text`.
+fn find_urls(text: &str) -> Vec<Range<usize>> {
+    const PREFIXES: &[&str] = &["https://", "http://", "www."];
+
+    let mut urls = Vec::new();
+    let mut i = 0;
+
+    while i < text.len() {
+        let rest = &text[i..];
+        let at_boundary = text[..i]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !c.is_alphanumeric());
+
+        let prefix = PREFIXES.iter().find(|p| rest.starts_with(*p));
+        let (Some(prefix), true) = (prefix, at_boundary) else {
+            i += rest.chars().next().map_or(1, |c| c.len_utf8());
+            continue;
+        };
+
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == '<')
+            .unwrap_or(rest.len());
+        let mut url = &rest[..len];
+
+        // punctuation closing the sentence is not a part of the URL
+        loop {
+            let trimmed =
+                url.trim_end_matches(['?', '!', '.', ',', ':', ';', '*', '_', '~', '\'', '"']);
+            let trimmed = if trimmed.ends_with(')')
+                && trimmed.matches(')').count() > trimmed.matches('(').count()
+            {
+                &trimmed[..trimmed.len() - 1]
+            } else {
+                trimmed
+            };
+
+            if trimmed.len() == url.len() {
+                break;
+            }
+            url = trimmed;
+        }
+
+        if url.len() > prefix.len() {
+            urls.push(i..i + url.len());
+        }
+        i += url.len().max(prefix.len());
+    }
+
+    urls
+}
+
+struct Markdown;
+
+impl Emitter for Markdown {
+    fn text(&self, text: &str) -> String {
+        escape(text)
+    }
+
+    fn paragraph(&self, lines: &[String]) -> String {
+        // hard line breaks keep the lines of a paragraph
+        lines
+            .iter()
+            .map(|l| escape_line_start(l))
+            .collect::<Vec<_>>()
+            .join("  \n")
+    }
+
+    fn inline(&self, handle: &Handle, parent: Format) -> String {
+        render_inline(handle, parent)
+    }
+
+    fn block(&self, handle: &Handle, format: Format) -> Option<String> {
+        match tag_of(handle).unwrap_or_default() {
+            tag @ ("h1" | "h2" | "h3" | "h4" | "h5" | "h6") => {
+                let level = tag[1..].parse::<usize>().unwrap();
+                let text = clean_lines(&render_inline_children(self, handle, format)).join(" ");
+                if text.is_empty() {
+                    Some(text)
+                } else {
+                    Some(format!("{} {}", "#".repeat(level), text))
+                }
+            }
+            "pre" => Some(render_code_block(handle)),
+            "table" => Some(render_table(handle, format)),
+            "hr" => Some("---".to_string()),
+            _ => None,
+        }
+    }
+
+    fn item_separator(&self, li: &Handle) -> &'static str {
+        // loose list items keep blank lines between paragraphs
+        if has_paragraph(li) { "\n\n" } else { "\n" }
+    }
+}
+
+/// Renders an inline element.
+fn render_inline(handle: &Handle, parent: Format) -> String {
+    let format = apply_format(handle, parent);
+
+    match tag_of(handle).unwrap_or_default() {
+        "br" => "\n".to_string(),
+        "img" => render_image(handle),
+        "input" => match get_attr(handle, "type").as_deref() {
+            Some("checkbox") if get_attr(handle, "checked").is_some() => "[x] ".to_string(),
+            Some("checkbox") => "[ ] ".to_string(),
+            _ => String::new(),
+        },
+        "a" => {
+            let text = clean_lines(&render_inline_children(&Markdown, handle, format)).join(" ");
+            match get_attr(handle, "href") {
+                Some(href) if text.is_empty() => format!("<{}>", href),
+                Some(href) if text == escape(&href) && href.contains("://") => {
+                    format!("<{}>", href)
+                }
+                Some(href) => {
+                    let title = get_attr(handle, "title")
+                        .map(|t| format!(" \"{}\"", t.replace('"', "\\\"")))
+                        .unwrap_or_default();
+                    format!("[{}]({}{})", text, link_destination(&href), title)
+                }
+                None => text,
+            }
+        }
+        tag => {
+            if format.code && !parent.code {
+                return code_span(&get_text(handle));
+            }
+
+            let inner = render_inline_children(&Markdown, handle, format);
+            if is_block(handle) {
+                // block in inline context, e.g. <div> in a table cell
+                format!("\n{}\n", inner)
+            } else if tag == "q" {
+                format!("\"{}\"", inner)
+            } else {
+                wrap_format(&inner, parent, format)
+            }
+        }
+    }
+}
+
+/// Surrounds `inner` with the markers of the formats turned on between `parent` and `format`.
+fn wrap_format(inner: &str, parent: Format, format: Format) -> String {
+    let mut open = String::new();
+    if format.bold && !parent.bold {
+        open.push_str("**");
+    }
+    if format.italic && !parent.italic {
+        open.push('*');
+    }
+    if format.strike && !parent.strike {
+        open.push_str("~~");
+    }
+
+    if open.is_empty() {
+        return inner.to_string();
+    }
+
+    let trimmed = inner.trim();
+    if trimmed.is_empty() {
+        return inner.to_string();
+    }
+
+    // markers must touch the text, keep the surrounding spaces outside
+    let leading = &inner[..inner.len() - inner.trim_start().len()];
+    let trailing = &inner[inner.trim_end().len()..];
+    let close = open.chars().rev().collect::<String>();
+
+    format!("{}{}{}{}{}", leading, open, trimmed, close, trailing)
+}
+
+fn render_image(handle: &Handle) -> String {
+    let Some(src) = get_attr(handle, "src") else {
+        return String::new();
+    };
+
+    let alt = escape(&get_attr(handle, "alt").unwrap_or_default());
+    let title = get_attr(handle, "title")
+        .map(|t| format!(" \"{}\"", t.replace('"', "\\\"")))
+        .unwrap_or_default();
+
+    format!("![{}]({}{})", alt, link_destination(&src), title)
+}
+
+fn link_destination(url: &str) -> String {
+    if url.contains(' ') || url.contains('(') || url.contains(')') {
+        format!("<{}>", url)
+    } else {
+        url.to_string()
+    }
+}
+
+fn code_span(code: &str) -> String {
+    let code = collapse_whitespace(code);
+    if code.trim().is_empty() {
+        return code;
+    }
+
+    let fence = "`".repeat(longest_run(&code, '`') + 1);
+    if code.starts_with('`') || code.ends_with('`') {
+        format!("{} {} {}", fence, code, fence)
+    } else {
+        format!("{}{}{}", fence, code, fence)
+    }
+}
+
+fn render_code_block(pre: &Handle) -> String {
+    let code = get_text(pre);
+    let code = code.strip_suffix('\n').unwrap_or(&code);
+
+    // <pre class="language-rust"> or <pre><code class="language-rust">
+    let code_element = pre
+        .children
+        .borrow()
+        .iter()
+        .find(|c| is_element(c, "code"))
+        .cloned();
+    let language = [Some(pre.clone()), code_element]
+        .iter()
+        .flatten()
+        .filter_map(|e| get_attr(e, "class"))
+        .flat_map(|c| {
+            c.split_whitespace()
+                .filter_map(|c| {
+                    c.strip_prefix("language-")
+                        .or_else(|| c.strip_prefix("lang-"))
+                        .map(|l| l.to_string())
+                })
+                .collect::<Vec<_>>()
+        })
+        .next()
+        .unwrap_or_default();
+
+    let fence = "`".repeat(longest_run(code, '`').max(2) + 1);
+    format!("{}{}\n{}\n{}", fence, language, code, fence)
+}
+
+fn has_paragraph(li: &Handle) -> bool {
+    li.children.borrow().iter().any(|c| is_element(c, "p"))
+}
+
+fn render_table(table: &Handle, format: Format) -> String {
+    let rows = get_rows(table);
+    let grid = get_grid(&rows);
+    if grid.is_empty() || grid[0].is_empty() {
+        return String::new();
+    }
+
+    let render_row = |row: &Vec<Option<GridCell>>| -> Vec<String> {
+        row.iter()
+            .map(|cell| match cell {
+                Some(cell) if !cell.spanned => {
+                    let cell_format = apply_format(&cell.handle, format);
+                    clean_lines(&render_inline_children(
+                        &Markdown,
+                        &cell.handle,
+                        cell_format,
+                    ))
+                    .join("<br>")
+                    .replace('|', "\\|")
+                }
+                _ => String::new(),
+            })
+            .collect()
+    };
+
+    // GFM tables always have a header row
+    let (header, body) = match get_headers(&rows, &grid) {
+        Some(_) => {
+            let header_i = rows
+                .iter()
+                .rposition(|r| get_parent(r).is_some_and(|p| is_element(&p, "thead")))
+                .unwrap_or(0);
+            (
+                render_row(&grid[header_i]),
+                grid[header_i + 1..].iter().map(render_row).collect(),
+            )
+        }
+        None => (
+            render_row(&grid[0]),
+            grid[1..].iter().map(render_row).collect::<Vec<_>>(),
+        ),
+    };
+
+    let aligns = grid
+        .iter()
+        .find(|row| row.iter().all(|c| c.is_some()))
+        .unwrap_or(&grid[0])
+        .iter()
+        .map(|cell| {
+            let align = cell.as_ref().and_then(|c| {
+                get_attr(&c.handle, "align").or_else(|| get_style(&c.handle, "text-align"))
+            });
+            match align.as_deref() {
+                Some("left") => ":---",
+                Some("center") => ":---:",
+                Some("right") => "---:",
+                _ => "---",
+            }
+            .to_string()
+        })
+        .collect::<Vec<_>>();
+
+    let mut lines = vec![table_line(&header), table_line(&aligns)];
+    lines.extend(body.iter().map(|r| table_line(r)));
+
+    lines.join("\n")
+}
+
+fn table_line(cells: &[String]) -> String {
+    format!("| {} |", cells.join(" | "))
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        // `<` starts inline HTML and `&` a character reference
+        if matches!(c, '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '&') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
+
+/// Escapes text at the start of a line that would otherwise start a block.
+fn escape_line_start(line: &str) -> String {
+    if line.starts_with('#') || line.starts_with('>') {
+        return format!("\\{}", line);
+    }
+
+    if line.starts_with("- ") || line.starts_with("+ ") || line.starts_with("~~~") {
+        return format!("\\{}", line);
+    }
+
+    // "---" is a thematic break, and "---" or "===" after a line a setext heading
+    // (`*` and `_` are escaped everywhere)
+    for marker in ['-', '='] {
+        if line.starts_with(marker) && line.chars().all(|c| c == marker || c == ' ' || c == '\t') {
+            return format!("\\{}", line);
+        }
+    }
+
+    // "1. " and "1) " are ordered lists
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits > 0 && (line[digits..].starts_with(". ") || line[digits..].starts_with(") ")) {
+        return format!("{}\\{}", &line[..digits], &line[digits..]);
+    }
+
+    line.to_string()
+}
+
+fn longest_run(text: &str, target: char) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in text.chars() {
+        if c == target {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+
+    longest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline() {
+        let html = r#"<p>Hello <b>bold</b>, <i>italic </i><s>gone</s> <code>a`b</code> <a href="https://example.com/" title="Example">link</a></p>"#;
+        assert_eq!(
+            "Hello **bold**, *italic* ~~gone~~ ``a`b`` [link](https://example.com/ \"Example\")",
+            html_to_markdown(html)
+        );
+
+        // Google Docs wraps the whole document in <b style="font-weight:normal">
+        let html = r#"<b style="font-weight:normal;"><p><span style="font-weight:700">strong</span> 1. *not* a list</p></b>"#;
+        assert_eq!("**strong** 1. \\*not\\* a list", html_to_markdown(html));
+
+        // literal text stays literal
+        let html = "<p>&lt;br&gt; is &amp;amp; in HTML</p>";
+        assert_eq!("\\<br> is \\&amp; in HTML", html_to_markdown(html));
+
+        let html = r#"<p><a href="https://example.com/">https://example.com/</a><br><img src="a.png" alt="A"></p>"#;
+        assert_eq!(
+            "<https://example.com/>  \n![A](a.png)",
+            html_to_markdown(html)
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let html = r#"<h1>Title</h1>
+<p>first</p>
+<blockquote><p>quote</p><p>second</p></blockquote>
+<pre><code class="language-rust">fn main() {
+    println!("```");
+}
+</code></pre>
+<hr>
+<p># not a heading</p>"#;
+
+        assert_eq!(
+            "# Title\n\nfirst\n\n> quote\n>\n> second\n\n````rust\nfn main() {\n    println!(\"```\");\n}\n````\n\n---\n\n\\# not a heading",
+            html_to_markdown(html)
+        );
+    }
+
+    #[test]
+    fn test_lists() {
+        let html = r#"<ul>
+<li>one</li>
+<li>two<ol start="3"><li>three</li><li><input type="checkbox" checked> four</li></ol></li>
+</ul>"#;
+
+        assert_eq!(
+            "- one\n- two\n  3. three\n  4. [x] four",
+            html_to_markdown(html)
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        let html = r#"<table>
+<thead><tr><th>Name</th><th align="right">Count</th></tr></thead>
+<tbody>
+<tr><td><a href="https://example.com/">foo</a></td><td>1</td></tr>
+<tr><td>a|b</td><td>line<br>break</td></tr>
+</tbody>
+</table>"#;
+
+        assert_eq!(
+            "| Name | Count |\n| --- | ---: |\n| [foo](https://example.com/) | 1 |\n| a\\|b | line<br>break |",
+            html_to_markdown(html)
+        );
+    }
+
+    #[test]
+    fn test_markdown_to_html() {
+        assert_eq!(
+            "<p>Hello <strong>world</strong> <del>old</del></p>\n",
+            markdown_to_html("Hello **world** ~~old~~")
+        );
+
+        let html = markdown_to_html("- [x] done\n- [ ] todo\n\n| a | b |\n| - | - |\n| 1 | 2 |");
+        assert!(html.contains(r#"type="checkbox""#));
+        assert!(html.contains("<th>a</th>"));
+        assert!(html.contains("<td>2</td>"));
+
+        let html = markdown_to_html(
+            "See https://example.com/a_(b). [https://example.org/](https://example.org/) `www.example.net`",
+        );
+        assert!(
+            html.contains(r#"<a href="https://example.com/a_(b)">https://example.com/a_(b)</a>."#)
+        );
+        assert!(html.contains(r#"<a href="https://example.org/">https://example.org/</a>"#));
+        assert!(html.contains("<code>www.example.net</code>"));
+    }
+
+    #[test]
+    fn test_escape_line_start() {
+        for line in [
+            "1) item", "~~~", "---", "***", "___", "===", "- - -", "# x", "2. y",
+        ] {
+            let html = format!("<p>foo<br>{}</p>", line);
+            assert_eq!(
+                format!("<p>foo<br />\n{}</p>\n", line),
+                markdown_to_html(&html_to_markdown(&html)),
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn test_find_urls() {
+        let text = "see www.example.com, (https://example.com/x) or xhttp://no.";
+        let urls = find_urls(text)
+            .into_iter()
+            .map(|r| &text[r])
+            .collect::<Vec<_>>();
+
+        assert_eq!(vec!["www.example.com", "https://example.com/x"], urls);
+    }
+}
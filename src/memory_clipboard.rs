@@ -0,0 +1,100 @@
+use crate::{
+    clip::{Clip, ClipboardFormat},
+    error::Error,
+};
+
+/// Clipboard kept in process memory, for tests.
+#[derive(Debug, Default)]
+pub struct MemoryClipboard {
+    formats: Vec<ClipboardFormat>,
+}
+
+impl MemoryClipboard {
+    pub fn with_data(formats: Vec<ClipboardFormat>) -> Self {
+        Self { formats }
+    }
+
+    pub fn formats(&self) -> &[ClipboardFormat] {
+        &self.formats
+    }
+
+    fn find(&self, format: &ClipboardFormat) -> Option<&ClipboardFormat> {
+        self.formats.iter().find(|e| e.is_same_format(format))
+    }
+}
+
+impl Clip for MemoryClipboard {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn determine_format(&self) -> Result<ClipboardFormat, Error> {
+        // HTML
+        if self.find(&ClipboardFormat::Html("".to_string())).is_some() {
+            return Ok(ClipboardFormat::Html("".to_string()));
+        }
+
+        // Plain text
+        if self.find(&ClipboardFormat::Text("".to_string())).is_some() {
+            return Ok(ClipboardFormat::Text("".to_string()));
+        }
+
+        // unsupported
+        Err(Error::new("Clipboard format not available"))
+    }
+
+    fn get_data(&mut self, format: &ClipboardFormat) -> Result<ClipboardFormat, Error> {
+        match self.find(format) {
+            Some(data) => Ok(data.clone()),
+            None => Err(Error::new("Clipboard format not available")),
+        }
+    }
+
+    fn set_data_list(&mut self, data: &[ClipboardFormat]) -> Result<(), Error> {
+        self.formats = data.to_vec();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_memory_clipboard() {
+        let mut clip = MemoryClipboard::with_data(vec![
+            ClipboardFormat::Text("foo".to_string()),
+            ClipboardFormat::Html("<b>foo</b>".to_string()),
+        ]);
+
+        assert!(matches!(
+            clip.determine_format().unwrap(),
+            ClipboardFormat::Html(_)
+        ));
+        assert_eq!(
+            "foo",
+            clip.get_data(&ClipboardFormat::Text("".to_string()))
+                .unwrap()
+                .to_string()
+        );
+
+        // setting data replaces every format
+        clip.set_data(&ClipboardFormat::Text("bar".to_string()))
+            .unwrap();
+        assert!(matches!(
+            clip.determine_format().unwrap(),
+            ClipboardFormat::Text(_)
+        ));
+        assert!(
+            clip.get_data(&ClipboardFormat::Html("".to_string()))
+                .is_err()
+        );
+        assert_eq!(1, clip.formats().len());
+    }
+
+    #[test]
+    fn test_empty_memory_clipboard() {
+        let clip = MemoryClipboard::new();
+        assert!(clip.determine_format().is_err());
+    }
+}
@@ -0,0 +1,174 @@
+//! Plain text rendering of HTML, put on the clipboard next to HTML results
+//!
+//! Tables become tab-separated rows, lists indented bullets and links `text (url)`.
+
+use markup5ever_rcdom::Handle;
+
+use crate::{
+    blocks::{Emitter, clean_lines, is_block, render_blocks, render_inline_children, tag_of},
+    html::{Format, GridCell, get_attr, get_grid, get_rows, get_text, parse_html},
+};
+
+/// Converts HTML to plain text.
+pub fn html_to_plain_text(html: &str) -> String {
+    let dom = parse_html(&html.to_string());
+
+    let mut blocks = Vec::new();
+    render_blocks(&PlainText, &dom.document, Format::default(), &mut blocks);
+
+    blocks.join("\n\n")
+}
+
+struct PlainText;
+
+impl Emitter for PlainText {
+    fn text(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    fn paragraph(&self, lines: &[String]) -> String {
+        lines.join("\n")
+    }
+
+    fn inline(&self, handle: &Handle, _: Format) -> String {
+        render_inline(handle)
+    }
+
+    fn block(&self, handle: &Handle, _: Format) -> Option<String> {
+        match tag_of(handle).unwrap_or_default() {
+            "pre" => Some(get_text(handle).trim_end_matches('\n').to_string()),
+            "table" => Some(render_table(handle)),
+            "hr" => Some("---".to_string()),
+            _ => None,
+        }
+    }
+}
+
+fn render_inline(handle: &Handle) -> String {
+    match tag_of(handle).unwrap_or_default() {
+        "br" => "\n".to_string(),
+        "img" => get_attr(handle, "alt").unwrap_or_default(),
+        "input" => match get_attr(handle, "type").as_deref() {
+            Some("checkbox") if get_attr(handle, "checked").is_some() => "[x] ".to_string(),
+            Some("checkbox") => "[ ] ".to_string(),
+            _ => String::new(),
+        },
+        "a" => {
+            let text = clean_lines(&render_inline_children(
+                &PlainText,
+                handle,
+                Format::default(),
+            ))
+            .join(" ");
+            link_text(text, get_attr(handle, "href"))
+        }
+        // a table in a cell
+        "table" => format!("\n{}\n", render_table(handle)),
+        _ => {
+            let inner = render_inline_children(&PlainText, handle, Format::default());
+            if is_block(handle) {
+                format!("\n{}\n", inner)
+            } else {
+                inner
+            }
+        }
+    }
+}
+
+fn link_text(text: String, href: Option<String>) -> String {
+    match href {
+        // in-page and script links mean nothing outside the page
+        Some(href) if href.starts_with('#') || href.starts_with("javascript:") => text,
+        Some(href) if text.is_empty() => href,
+        Some(href) if text == href || Some(text.as_str()) == href.strip_prefix("mailto:") => text,
+        Some(href) => format!("{} ({})", text, href),
+        None => text,
+    }
+}
+
+/// Renders a table as tab-separated rows, slots covered by spans are left empty.
+fn render_table(table: &Handle) -> String {
+    let rows = get_rows(table);
+    let grid = get_grid(&rows);
+
+    grid.iter()
+        .map(|row: &Vec<Option<GridCell>>| {
+            row.iter()
+                .map(|cell| match cell {
+                    Some(cell) if !cell.spanned => clean_lines(&render_inline_children(
+                        &PlainText,
+                        &cell.handle,
+                        Format::default(),
+                    ))
+                    .join(" ")
+                    .replace('\t', " "),
+                    _ => String::new(),
+                })
+                .collect::<Vec<_>>()
+                .join("\t")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inline() {
+        assert_eq!(
+            "see example (https://example.com/)\nnext line",
+            html_to_plain_text(
+                r#"<p>see <a href="https://example.com/">example</a><br>next  line</p>"#
+            )
+        );
+        assert_eq!(
+            "https://example.com/ a@example.com top",
+            html_to_plain_text(
+                r##"<a href="https://example.com/">https://example.com/</a> <a href="mailto:a@example.com">a@example.com</a> <a href="#top">top</a>"##
+            )
+        );
+        assert_eq!(
+            "[x] done",
+            html_to_plain_text(r#"<input type="checkbox" checked> done"#)
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        assert_eq!(
+            "Title\n\nfirst\n\n> quoted\n\nfn main() {\n    x\n}",
+            html_to_plain_text(
+                "<h1>Title</h1><p>first</p><blockquote><p>quoted</p></blockquote><pre>fn main() {\n    x\n}\n</pre><script>x()</script>"
+            )
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(
+            "- foo\n- bar (https://example.com/)\n  9. baz\n  10. qux",
+            html_to_plain_text(
+                r#"<ul><li>foo</li><li><a href="https://example.com/">bar</a><ol start="9"><li>baz</li><li>qux</li></ol></li></ul>"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_table() {
+        assert_eq!(
+            "Name\tCount\nfoo (https://example.com/)\t1\nmerged\t\nbar\tx y",
+            html_to_plain_text(
+                r#"<table>
+                <thead><tr><th>Name</th><th>Count</th></tr></thead>
+                <tbody>
+                <tr><td><a href="https://example.com/">foo</a></td><td>1</td></tr>
+                <tr><td colspan="2">merged</td></tr>
+                <tr><td>bar</td><td>x<br>y</td></tr>
+                </tbody>
+                </table>"#
+            )
+        );
+    }
+}
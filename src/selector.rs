@@ -0,0 +1,573 @@
+//! CSS selectors on the parsed HTML tree
+//!
+//! Supported:
+//! - type `a`, universal `*`, class `.issue-link`, id `#title`
+//! - attribute `[href]`, `[type=text]`, `[class~=a]`, `[lang|=en]`, `[href^=https]`, `[href$=".pdf"]`, `[href*=example]`
+//! - `:nth-child(2n+1)`, `:nth-child(odd)`, `:first-child`, `:last-child`
+//! - combinators: descendant ` `, child `>`, adjacent sibling `+`, general sibling `~`
+//! - selector lists `h1, h2`
+
+use markup5ever_rcdom::{Handle, NodeData};
+
+use crate::{error::Error, html::get_parent};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Selector {
+    complexes: Vec<Complex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Complex {
+    compounds: Vec<Compound>,
+    /// `combinators[i]` joins `compounds[i]` and `compounds[i + 1]`
+    combinators: Vec<Combinator>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Combinator {
+    Descendant,
+    Child,
+    Adjacent,
+    Sibling,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Compound {
+    tag: Option<String>,
+    ids: Vec<String>,
+    classes: Vec<String>,
+    attrs: Vec<Attr>,
+    pseudos: Vec<Pseudo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Attr {
+    name: String,
+    op: Option<(AttrOp, String)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AttrOp {
+    Equals,
+    Includes,
+    DashMatch,
+    Prefix,
+    Suffix,
+    Substring,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pseudo {
+    /// an+b
+    NthChild(i64, i64),
+    LastChild,
+}
+
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+}
+
+impl Selector {
+    pub fn parse(css: &str) -> Result<Self, Error> {
+        let mut parser = Parser {
+            chars: css.chars().peekable(),
+        };
+
+        let mut complexes = vec![parser.complex()?];
+        while parser.eat(',') {
+            complexes.push(parser.complex()?);
+        }
+
+        match parser.chars.next() {
+            None => Ok(Self { complexes }),
+            Some(c) => Err(Error::new(format!(
+                "Unexpected '{}' in selector: {}",
+                c, css
+            ))),
+        }
+    }
+
+    pub fn matches(&self, handle: &Handle) -> bool {
+        self.complexes.iter().any(|c| c.matches(handle))
+    }
+
+    /// Returns the descendants of `root` matching the selector, in document order.
+    pub fn select(&self, root: &Handle) -> Vec<Handle> {
+        let mut result = Vec::new();
+        self.collect(root, &mut result);
+        result
+    }
+
+    fn collect(&self, handle: &Handle, result: &mut Vec<Handle>) {
+        for child in handle.children.borrow().iter() {
+            if self.matches(child) {
+                result.push(child.clone());
+            }
+            self.collect(child, result);
+        }
+    }
+}
+
+impl Complex {
+    fn matches(&self, handle: &Handle) -> bool {
+        self.matches_at(self.compounds.len() - 1, handle)
+    }
+
+    fn matches_at(&self, index: usize, handle: &Handle) -> bool {
+        if !self.compounds[index].matches(handle) {
+            return false;
+        }
+
+        if index == 0 {
+            return true;
+        }
+
+        match self.combinators[index - 1] {
+            Combinator::Child => {
+                parent_element(handle).is_some_and(|p| self.matches_at(index - 1, &p))
+            }
+            Combinator::Descendant => {
+                let mut current = parent_element(handle);
+                while let Some(p) = current {
+                    if self.matches_at(index - 1, &p) {
+                        return true;
+                    }
+                    current = parent_element(&p);
+                }
+                false
+            }
+            Combinator::Adjacent => {
+                let siblings = preceding_elements(handle);
+                siblings
+                    .last()
+                    .is_some_and(|s| self.matches_at(index - 1, s))
+            }
+            Combinator::Sibling => preceding_elements(handle)
+                .iter()
+                .any(|s| self.matches_at(index - 1, s)),
+        }
+    }
+}
+
+impl Compound {
+    fn matches(&self, handle: &Handle) -> bool {
+        let (name, attrs) = match handle.data {
+            NodeData::Element {
+                ref name,
+                ref attrs,
+                ..
+            } => (name, attrs.borrow()),
+            _ => return false,
+        };
+
+        if self
+            .tag
+            .as_ref()
+            .is_some_and(|tag| name.local.as_ref() != tag)
+        {
+            return false;
+        }
+
+        let get = |key: &str| {
+            attrs
+                .iter()
+                .find(|a| a.name.local.as_ref() == key)
+                .map(|a| a.value.to_string())
+        };
+
+        if !self.ids.is_empty() {
+            let id = get("id");
+            if !self.ids.iter().all(|e| id.as_deref() == Some(e.as_str())) {
+                return false;
+            }
+        }
+
+        if !self.classes.is_empty() {
+            let class = get("class").unwrap_or_default();
+            let classes = class.split_ascii_whitespace().collect::<Vec<_>>();
+            if !self.classes.iter().all(|e| classes.contains(&e.as_str())) {
+                return false;
+            }
+        }
+
+        for attr in self.attrs.iter() {
+            let value = match get(&attr.name) {
+                Some(v) => v,
+                None => return false,
+            };
+
+            let matched = match &attr.op {
+                None => true,
+                Some((AttrOp::Equals, e)) => value == *e,
+                Some((AttrOp::Includes, e)) => value.split_ascii_whitespace().any(|v| v == e),
+                Some((AttrOp::DashMatch, e)) => {
+                    value == *e || value.starts_with(&format!("{}-", e))
+                }
+                Some((AttrOp::Prefix, e)) => !e.is_empty() && value.starts_with(e.as_str()),
+                Some((AttrOp::Suffix, e)) => !e.is_empty() && value.ends_with(e.as_str()),
+                Some((AttrOp::Substring, e)) => !e.is_empty() && value.contains(e.as_str()),
+            };
+
+            if !matched {
+                return false;
+            }
+        }
+
+        self.pseudos.iter().all(|p| p.matches(handle))
+    }
+}
+
+impl Pseudo {
+    fn matches(&self, handle: &Handle) -> bool {
+        match *self {
+            Pseudo::NthChild(a, b) => {
+                // 1-based index among the element siblings
+                let index = preceding_elements(handle).len() as i64 + 1;
+                if a == 0 {
+                    index == b
+                } else {
+                    let n = index - b;
+                    n % a == 0 && n / a >= 0
+                }
+            }
+            Pseudo::LastChild => match get_parent(handle) {
+                Some(parent) => parent
+                    .children
+                    .borrow()
+                    .iter()
+                    .rev()
+                    .find(|c| matches!(c.data, NodeData::Element { .. }))
+                    .is_some_and(|c| std::rc::Rc::ptr_eq(c, handle)),
+                None => false,
+            },
+        }
+    }
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) -> bool {
+        let mut skipped = false;
+        while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+            self.chars.next();
+            skipped = true;
+        }
+        skipped
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.peek() == Some(&expected) {
+            self.chars.next();
+            self.skip_whitespace();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn complex(&mut self) -> Result<Complex, Error> {
+        self.skip_whitespace();
+
+        let mut compounds = vec![self.compound()?];
+        let mut combinators = vec![];
+
+        loop {
+            let whitespace = self.skip_whitespace();
+            let combinator = match self.chars.peek() {
+                Some('>') => Combinator::Child,
+                Some('+') => Combinator::Adjacent,
+                Some('~') => Combinator::Sibling,
+                Some(',') | None => break,
+                Some(_) if whitespace => Combinator::Descendant,
+                Some(c) => return Err(Error::new(format!("Unexpected '{}' in selector", c))),
+            };
+
+            if combinator != Combinator::Descendant {
+                self.chars.next();
+                self.skip_whitespace();
+            }
+
+            combinators.push(combinator);
+            compounds.push(self.compound()?);
+        }
+
+        Ok(Complex {
+            compounds,
+            combinators,
+        })
+    }
+
+    fn compound(&mut self) -> Result<Compound, Error> {
+        let mut compound = Compound::default();
+
+        let mut universal = false;
+        match self.chars.peek() {
+            Some('*') => {
+                self.chars.next();
+                universal = true;
+            }
+            Some(c) if is_ident_char(*c) => {
+                compound.tag = Some(self.ident()?.to_ascii_lowercase());
+            }
+            _ => {}
+        }
+
+        loop {
+            match self.chars.peek() {
+                Some('#') => {
+                    self.chars.next();
+                    compound.ids.push(self.ident()?);
+                }
+                Some('.') => {
+                    self.chars.next();
+                    compound.classes.push(self.ident()?);
+                }
+                Some('[') => {
+                    self.chars.next();
+                    compound.attrs.push(self.attr()?);
+                }
+                Some(':') => {
+                    self.chars.next();
+                    compound.pseudos.push(self.pseudo()?);
+                }
+                _ => break,
+            }
+        }
+
+        if !universal && compound == Compound::default() {
+            return Err(Error::new("Empty selector"));
+        }
+
+        Ok(compound)
+    }
+
+    fn ident(&mut self) -> Result<String, Error> {
+        let mut ident = String::new();
+        while let Some(c) = self.chars.peek() {
+            if *c == '\\' {
+                self.chars.next();
+                match self.chars.next() {
+                    Some(c) => ident.push(c),
+                    None => break,
+                }
+            } else if is_ident_char(*c) {
+                ident.push(*c);
+                self.chars.next();
+            } else {
+                break;
+            }
+        }
+
+        if ident.is_empty() {
+            return Err(Error::new("Expected an identifier in selector"));
+        }
+        Ok(ident)
+    }
+
+    fn attr(&mut self) -> Result<Attr, Error> {
+        self.skip_whitespace();
+        let name = self.ident()?.to_ascii_lowercase();
+        self.skip_whitespace();
+
+        let op = match self.chars.next() {
+            Some(']') => return Ok(Attr { name, op: None }),
+            Some('=') => AttrOp::Equals,
+            Some(c) => {
+                let op = match c {
+                    '~' => AttrOp::Includes,
+                    '|' => AttrOp::DashMatch,
+                    '^' => AttrOp::Prefix,
+                    '$' => AttrOp::Suffix,
+                    '*' => AttrOp::Substring,
+                    _ => {
+                        return Err(Error::new(format!(
+                            "Unexpected '{}' in attribute selector",
+                            c
+                        )));
+                    }
+                };
+                if self.chars.next() != Some('=') {
+                    return Err(Error::new("Expected '=' in attribute selector"));
+                }
+                op
+            }
+            None => return Err(Error::new("Unterminated attribute selector")),
+        };
+
+        self.skip_whitespace();
+        let value = match self.chars.peek() {
+            Some(q) if *q == '"' || *q == '\'' => {
+                let quote = *q;
+                self.chars.next();
+                let mut value = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some('\\') => {
+                            if let Some(c) = self.chars.next() {
+                                value.push(c);
+                            }
+                        }
+                        Some(c) if c == quote => break,
+                        Some(c) => value.push(c),
+                        None => return Err(Error::new("Unterminated string in selector")),
+                    }
+                }
+                value
+            }
+            _ => self.ident()?,
+        };
+
+        self.skip_whitespace();
+        if self.chars.next() != Some(']') {
+            return Err(Error::new("Expected ']' in attribute selector"));
+        }
+
+        Ok(Attr {
+            name,
+            op: Some((op, value)),
+        })
+    }
+
+    fn pseudo(&mut self) -> Result<Pseudo, Error> {
+        let name = self.ident()?.to_ascii_lowercase();
+        match name.as_str() {
+            "first-child" => Ok(Pseudo::NthChild(0, 1)),
+            "last-child" => Ok(Pseudo::LastChild),
+            "nth-child" => {
+                if self.chars.next() != Some('(') {
+                    return Err(Error::new("Expected '(' after :nth-child"));
+                }
+
+                let mut arg = String::new();
+                loop {
+                    match self.chars.next() {
+                        Some(')') => break,
+                        Some(c) => arg.push(c),
+                        None => return Err(Error::new("Unterminated :nth-child")),
+                    }
+                }
+
+                let (a, b) = parse_nth(&arg)?;
+                Ok(Pseudo::NthChild(a, b))
+            }
+            _ => Err(Error::new(format!("Unsupported pseudo-class :{}", name))),
+        }
+    }
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '-' || c == '_' || c == '\\' || !c.is_ascii()
+}
+
+/// Parses `an+b`, `odd` and `even`.
+fn parse_nth(arg: &str) -> Result<(i64, i64), Error> {
+    let arg = arg
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let invalid = || Error::new(format!("Invalid :nth-child({})", arg));
+
+    match arg.as_str() {
+        "odd" => return Ok((2, 1)),
+        "even" => return Ok((2, 0)),
+        _ => {}
+    }
+
+    match arg.split_once('n') {
+        Some((a, b)) => {
+            let a = match a {
+                "" | "+" => 1,
+                "-" => -1,
+                _ => a.parse::<i64>().map_err(|_| invalid())?,
+            };
+            let b = match b {
+                "" => 0,
+                _ => b
+                    .trim_start_matches('+')
+                    .parse::<i64>()
+                    .map_err(|_| invalid())?,
+            };
+            Ok((a, b))
+        }
+        None => Ok((0, arg.parse::<i64>().map_err(|_| invalid())?)),
+    }
+}
+
+fn parent_element(handle: &Handle) -> Option<Handle> {
+    get_parent(handle).filter(|p| matches!(p.data, NodeData::Element { .. }))
+}
+
+fn preceding_elements(handle: &Handle) -> Vec<Handle> {
+    let parent = match get_parent(handle) {
+        Some(p) => p,
+        None => return vec![],
+    };
+
+    let children = parent.children.borrow();
+    let mut result = Vec::new();
+    for child in children.iter() {
+        if std::rc::Rc::ptr_eq(child, handle) {
+            break;
+        }
+        if let NodeData::Element { .. } = child.data {
+            result.push(child.clone());
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::html::{get_text, parse_html};
+
+    fn select_text(html: &str, css: &str) -> Vec<String> {
+        let dom = parse_html(&html.to_string());
+        Selector::parse(css)
+            .unwrap()
+            .select(&dom.document)
+            .iter()
+            .map(get_text)
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let html = r#"<div id="main"><ul class="list"><li><a class="issue-link" href="https://example.com/1">A-1</a></li><li><a href="https://example.com/2" class="other">A-2</a></li><li><a class="issue-link done" href="/3">A-3</a></li></ul><p lang="en-US">para</p></div>"#;
+
+        assert_eq!(vec!["A-1", "A-3"], select_text(html, "a.issue-link"));
+        assert_eq!(vec!["A-3"], select_text(html, "a.issue-link.done"));
+        assert_eq!(
+            vec!["A-1", "A-2", "A-3"],
+            select_text(html, "#main ul > li a")
+        );
+        assert_eq!(Vec::<String>::new(), select_text(html, "#main > li"));
+        assert_eq!(
+            vec!["A-1", "A-2"],
+            select_text(html, r#"a[href^="https://"]"#)
+        );
+        assert_eq!(vec!["A-3"], select_text(html, "a[href$='/3']"));
+        assert_eq!(vec!["A-2"], select_text(html, "a[class~=other]"));
+        assert_eq!(vec!["para"], select_text(html, "[lang|=en]"));
+        assert_eq!(vec!["A-1", "A-3"], select_text(html, "li:nth-child(odd)"));
+        assert_eq!(vec!["A-2"], select_text(html, "li:nth-child(2n)"));
+        assert_eq!(vec!["A-1"], select_text(html, "li:first-child"));
+        assert_eq!(vec!["A-3"], select_text(html, "li:last-child"));
+        assert_eq!(vec!["A-2", "A-3"], select_text(html, "li:nth-child(n+2)"));
+        assert_eq!(vec!["A-2"], select_text(html, "li:first-child + li"));
+        assert_eq!(vec!["para"], select_text(html, "ul ~ p"));
+        // document order, no duplicates
+        assert_eq!(vec!["A-1", "para"], select_text(html, "p, li:first-child"));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("a >").is_err());
+        assert!(Selector::parse("a[href").is_err());
+        assert!(Selector::parse("li:nth-child(x)").is_err());
+        assert!(Selector::parse("a:hover").is_err());
+    }
+}
@@ -1,9 +1,15 @@
-use mlua::{Lua, Result, Table, Value};
+use std::collections::HashMap;
+
+use mlua::{Function, LightUserData, Lua, Result, Table, Value};
 use serde_json::{Map, Value as JsonValue};
 
+pub fn parse_json(json_str: &str) -> Result<JsonValue> {
+    serde_json::from_str(json_str)
+        .map_err(|e| mlua::Error::RuntimeError(format!("JSON parse error: {}", e)))
+}
+
 pub fn json_str_to_lua_table(lua: &Lua, json_str: &str) -> Result<Table> {
-    let json_value: JsonValue = serde_json::from_str(json_str)
-        .map_err(|e| mlua::Error::RuntimeError(format!("JSON parse error: {}", e)))?;
+    let json_value = parse_json(json_str)?;
 
     let lua_value = json_to_lua(lua, &json_value)?;
     match lua_value {
@@ -14,11 +20,69 @@ pub fn json_str_to_lua_table(lua: &Lua, json_str: &str) -> Result<Table> {
     }
 }
 
+/// `json_null`, which stands for `null` in tables where `nil` would drop the key or leave a hole.
+pub fn json_null() -> Value {
+    Value::LightUserData(LightUserData(std::ptr::null_mut()))
+}
+
 pub fn lua_table_to_json_str(lua: &Lua, table: Table) -> Result<String> {
     let json_value = lua_to_json(lua, Value::Table(table))?;
     serde_json::to_string(&json_value).map_err(|e| mlua::Error::RuntimeError(e.to_string()))
 }
 
+/// Metatable field telling `"array"` from `"object"`, for empty tables in particular.
+const JSON_TYPE: &str = "__jsontype";
+/// Metatable field listing the keys of an object in the order they were added.
+const JSON_KEYS: &str = "__jsonkeys";
+const JSON_NEWINDEX: &str = "qlp.json_newindex";
+
+/// `__newindex` recording the order keys are added in, a key removed and added again is listed twice.
+fn json_newindex(lua: &Lua) -> Result<Function> {
+    if let Ok(f) = lua.named_registry_value::<Function>(JSON_NEWINDEX) {
+        return Ok(f);
+    }
+
+    let f = lua.create_function(|_, (table, key, value): (Table, Value, Value)| {
+        if !value.is_nil()
+            && let Some(mt) = table.metatable()
+            && let Ok(keys) = mt.raw_get::<Table>(JSON_KEYS)
+        {
+            keys.raw_push(key.clone())?;
+        }
+        table.raw_set(key, value)
+    })?;
+    lua.set_named_registry_value(JSON_NEWINDEX, &f)?;
+
+    Ok(f)
+}
+
+/// Marks `table` as a JSON `"array"` or `"object"`, objects keep the order keys are added in.
+pub fn set_json_type(lua: &Lua, table: &Table, json_type: &str) -> Result<()> {
+    let mt = lua.create_table()?;
+    mt.raw_set(JSON_TYPE, json_type)?;
+
+    if json_type == "object" {
+        // keys already there come first, in name order
+        let mut keys = table
+            .pairs::<Value, Value>()
+            .map(|p| json_key(&p?.0))
+            .collect::<Result<Vec<_>>>()?;
+        keys.sort();
+
+        mt.raw_set(JSON_KEYS, lua.create_sequence_from(keys)?)?;
+        mt.raw_set("__newindex", json_newindex(lua)?)?;
+    }
+
+    table.set_metatable(Some(mt))
+}
+
+fn get_json_type(table: &Table) -> Option<String> {
+    table
+        .metatable()
+        .and_then(|mt| mt.raw_get::<Option<String>>(JSON_TYPE).ok().flatten())
+}
+
+/// Converts `json`, `null` is `nil` at the top and [`json_null`] in arrays and objects.
 pub fn json_to_lua(lua: &Lua, json: &JsonValue) -> Result<Value> {
     match json {
         JsonValue::Null => Ok(Value::Nil),
@@ -36,20 +100,29 @@ pub fn json_to_lua(lua: &Lua, json: &JsonValue) -> Result<Value> {
         JsonValue::Array(arr) => {
             let table = lua.create_table()?;
             for (i, v) in arr.iter().enumerate() {
-                table.set(i + 1, json_to_lua(lua, v)?)?;
+                table.set(i + 1, json_element_to_lua(lua, v)?)?;
             }
+            set_json_type(lua, &table, "array")?;
             Ok(Value::Table(table))
         }
         JsonValue::Object(obj) => {
             let table = lua.create_table()?;
+            set_json_type(lua, &table, "object")?;
             for (k, v) in obj.iter() {
-                table.set(k.as_str(), json_to_lua(lua, v)?)?;
+                table.set(k.as_str(), json_element_to_lua(lua, v)?)?;
             }
             Ok(Value::Table(table))
         }
     }
 }
 
+fn json_element_to_lua(lua: &Lua, json: &JsonValue) -> Result<Value> {
+    match json {
+        JsonValue::Null => Ok(json_null()),
+        _ => json_to_lua(lua, json),
+    }
+}
+
 pub fn lua_to_json(lua: &Lua, value: Value) -> Result<JsonValue> {
     match value {
         Value::Nil => Ok(JsonValue::Null),
@@ -59,19 +132,25 @@ pub fn lua_to_json(lua: &Lua, value: Value) -> Result<JsonValue> {
             serde_json::Number::from_f64(n)
                 .ok_or_else(|| mlua::Error::RuntimeError("Invalid number".into()))?,
         )),
+        Value::LightUserData(ud) if ud.0.is_null() => Ok(JsonValue::Null),
         Value::String(s) => Ok(JsonValue::String(s.to_str()?.to_string())),
         Value::Table(table) => {
-            if is_array(lua, &table)? {
+            let is_array = match get_json_type(&table).as_deref() {
+                Some("array") => true,
+                Some("object") => false,
+                _ => is_array(lua, &table)?,
+            };
+
+            if is_array {
+                // holes of sparse arrays are null
                 let mut arr = Vec::new();
-                for pair in table.pairs::<i64, Value>() {
-                    let (_, v) = pair?;
-                    arr.push(lua_to_json(lua, v)?);
+                for i in 1..=array_len(&table)? {
+                    arr.push(lua_to_json(lua, table.raw_get::<Value>(i)?)?);
                 }
                 Ok(JsonValue::Array(arr))
             } else {
                 let mut obj = Map::new();
-                for pair in table.pairs::<String, Value>() {
-                    let (k, v) = pair?;
+                for (k, v) in object_entries(&table)? {
                     obj.insert(k, lua_to_json(lua, v)?);
                 }
                 Ok(JsonValue::Object(obj))
@@ -81,18 +160,118 @@ pub fn lua_to_json(lua: &Lua, value: Value) -> Result<JsonValue> {
     }
 }
 
+/// Sorts the keys of every object in `value`.
+pub fn sort_json_keys(value: &mut JsonValue) {
+    match value {
+        JsonValue::Array(arr) => arr.iter_mut().for_each(sort_json_keys),
+        JsonValue::Object(obj) => {
+            let mut entries = std::mem::take(obj).into_iter().collect::<Vec<_>>();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            for (k, mut v) in entries {
+                sort_json_keys(&mut v);
+                obj.insert(k, v);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn json_key(key: &Value) -> Result<String> {
+    match key {
+        Value::String(s) => Ok(s.to_str()?.to_string()),
+        Value::Integer(i) => Ok(i.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(mlua::Error::RuntimeError(
+            "JSON object keys must be strings or numbers".into(),
+        )),
+    }
+}
+
+/// Entries in the order keys were last added if known, the rest after them in name order.
+fn object_entries(table: &Table) -> Result<Vec<(String, Value)>> {
+    let mut entries = table
+        .pairs::<Value, Value>()
+        .map(|p| {
+            let (k, v) = p?;
+            Ok((json_key(&k)?, v))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    entries.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(mt) = table.metatable()
+        && let Ok(keys) = mt.raw_get::<Table>(JSON_KEYS)
+    {
+        // a key removed and added again is listed again, the later place wins
+        let mut order = HashMap::new();
+        for (i, k) in keys.sequence_values::<Value>().enumerate() {
+            order.insert(json_key(&k?)?, i);
+        }
+        entries.sort_by_key(|(k, _)| order.get(k).copied().unwrap_or(usize::MAX));
+    }
+
+    Ok(entries)
+}
+
+/// Length of an array, with at most as many holes as values.
+fn array_len(table: &Table) -> Result<i64> {
+    let mut count = 0;
+    let mut max = 0;
+    for pair in table.pairs::<Value, Value>() {
+        if let (Value::Integer(i), _) = pair?
+            && i > 0
+        {
+            count += 1;
+            max = max.max(i);
+        }
+    }
+
+    if max > count * 2 {
+        return Err(mlua::Error::RuntimeError(format!(
+            "array has too many holes to write: {} values up to index {}",
+            count, max
+        )));
+    }
+
+    Ok(max)
+}
+
+/// Positive integer keys only, with at most as many holes as values.
 fn is_array(_: &Lua, table: &Table) -> Result<bool> {
-    let mut expected = 1;
+    let mut count = 0;
+    let mut max = 0;
     for pair in table.pairs::<Value, Value>() {
         let (k, _) = pair?;
-        if let Value::Integer(i) = k {
-            if i != expected {
-                return Ok(false);
+        match k {
+            Value::Integer(i) if i > 0 => {
+                count += 1;
+                max = max.max(i);
             }
-            expected += 1;
-        } else {
-            return Ok(false);
+            _ => return Ok(false),
         }
     }
-    Ok(true)
+
+    Ok(max <= count * 2)
+}
+
+/// The text of a cell: strings, numbers and booleans, or a cell of `qlp.tables`; nil is empty.
+pub fn lua_value_to_text(value: Value) -> Result<String> {
+    match value {
+        Value::Nil => Ok(String::new()),
+        Value::String(s) => Ok(s.to_str()?.to_string()),
+        Value::Integer(n) => Ok(n.to_string()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Boolean(b) => Ok(b.to_string()),
+        Value::Table(t) => lua_value_to_text(t.get::<Value>("text")?),
+        _ => Err(mlua::Error::RuntimeError(
+            "cells must be strings, numbers, booleans or cells of qlp.tables".to_string(),
+        )),
+    }
+}
+
+/// The rows of a table of `qlp.tables`, or `table` itself as a list of rows.
+pub fn lua_table_rows(table: &Table) -> Result<Table> {
+    Ok(table
+        .get::<Option<Table>>("rows")?
+        .unwrap_or_else(|| table.clone()))
 }
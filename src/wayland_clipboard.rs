@@ -0,0 +1,107 @@
+use std::io::Read;
+
+use wl_clipboard_rs::{
+    copy::{self, MimeSource, Options, Source},
+    paste::{self, ClipboardType, Seat, get_contents, get_mime_types},
+};
+
+use crate::error::Error;
+
+/// Clipboard access through the Wayland data-control protocol.
+pub struct WaylandClipboard {}
+
+impl WaylandClipboard {
+    /// Connects to the compositor and checks that data-control is available.
+    pub fn new() -> Result<Self, Error> {
+        let instance = Self {};
+        instance.targets()?;
+        Ok(instance)
+    }
+
+    /// Returns the MIME types offered by the current selection.
+    pub fn targets(&self) -> Result<Vec<String>, Error> {
+        match get_mime_types(ClipboardType::Regular, Seat::Unspecified) {
+            Ok(types) => Ok(types.into_iter().collect()),
+            Err(paste::Error::NoSeats)
+            | Err(paste::Error::ClipboardEmpty)
+            | Err(paste::Error::NoMimeType) => Ok(Vec::new()),
+            Err(e) => Err(Error::new(format!(
+                "Failed to get Wayland MIME types: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Reads the current selection as `target`.
+    ///
+    /// Returns `None` when the clipboard is empty or does not offer `target`.
+    pub fn read(&self, target: &str) -> Result<Option<Vec<u8>>, Error> {
+        match get_contents(
+            ClipboardType::Regular,
+            Seat::Unspecified,
+            paste::MimeType::Specific(target),
+        ) {
+            Ok((mut pipe, _)) => {
+                let mut contents = vec![];
+                pipe.read_to_end(&mut contents)
+                    .map_err(|e| Error::new(format!("Failed to read Wayland pipe: {}", e)))?;
+                Ok(Some(contents))
+            }
+            Err(paste::Error::NoSeats)
+            | Err(paste::Error::ClipboardEmpty)
+            | Err(paste::Error::NoMimeType) => Ok(None),
+            Err(e) => Err(Error::new(format!(
+                "Failed to get Wayland clipboard contents: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Replaces the selection with `contents` as (MIME type, data) pairs.
+    ///
+    /// The data is served by a background process forked by `wl-clipboard-rs`.
+    pub fn write(&self, contents: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        let sources = contents
+            .into_iter()
+            .map(|(mime_type, data)| MimeSource {
+                source: Source::Bytes(data.into_boxed_slice()),
+                mime_type: copy::MimeType::Specific(mime_type),
+            })
+            .collect();
+
+        Options::new()
+            .copy_multi(sources)
+            .map_err(|e| Error::new(format!("Failed to set Wayland clipboard contents: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "requires a compositor with data-control (e.g. headless sway)"]
+    fn test_write_and_read() {
+        let clipboard = WaylandClipboard::new().unwrap();
+
+        clipboard
+            .write(vec![
+                ("text/html".to_string(), b"<b>qlp</b>".to_vec()),
+                ("text/plain;charset=utf-8".to_string(), b"qlp".to_vec()),
+            ])
+            .unwrap();
+
+        let targets = clipboard.targets().unwrap();
+        assert!(targets.contains(&"text/html".to_string()));
+
+        assert_eq!(
+            Some(b"<b>qlp</b>".to_vec()),
+            clipboard.read("text/html").unwrap()
+        );
+        assert_eq!(
+            Some(b"qlp".to_vec()),
+            clipboard.read("text/plain;charset=utf-8").unwrap()
+        );
+        assert_eq!(None, clipboard.read("image/png").unwrap());
+    }
+}
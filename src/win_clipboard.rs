@@ -1,14 +1,18 @@
 use crate::error::Error;
 
-use windows::Win32::{
-    Foundation::{HANDLE, HGLOBAL, HWND},
-    System::{
-        DataExchange::{
-            CloseClipboard, EmptyClipboard, EnumClipboardFormats, GetClipboardData,
-            GetClipboardFormatNameW, IsClipboardFormatAvailable, OpenClipboard, SetClipboardData,
+use windows::{
+    Win32::{
+        Foundation::{HANDLE, HGLOBAL, HWND},
+        System::{
+            DataExchange::{
+                CloseClipboard, EmptyClipboard, EnumClipboardFormats, GetClipboardData,
+                GetClipboardFormatNameW, IsClipboardFormatAvailable, OpenClipboard,
+                RegisterClipboardFormatW, SetClipboardData,
+            },
+            Ole::{CF_OEMTEXT, CF_UNICODETEXT, CLIPBOARD_FORMAT},
         },
-        Ole::{CF_OEMTEXT, CF_UNICODETEXT, CLIPBOARD_FORMAT},
     },
+    core::w,
 };
 
 pub struct WinClipboard {
@@ -32,31 +36,27 @@ impl WinClipboard {
     }
 
     pub fn new_wth_html_text() -> Self {
-        static HTML_FORMAT: &str = "HTML Format";
-
-        let mut initial = WinClipboard::new_with_unicode_text();
-        if initial.open().is_err() {
-            panic!("Failed to open clipboard");
+        // registering returns the existing id, even if the clipboard holds no HTML now
+        let format = unsafe { RegisterClipboardFormatW(w!("HTML Format")) };
+        if format == 0 {
+            panic!("HTML Format not found");
         }
 
-        let formats = initial.enumerate();
-        let formats = formats
-            .iter()
-            .filter(|cf| {
-                initial
-                    .resolve_clipboard_format_name(cf)
-                    .unwrap_or(String::default())
-                    == HTML_FORMAT
-            })
-            .collect::<Vec<_>>();
+        WinClipboard {
+            opened: false,
+            clipboard_format: CLIPBOARD_FORMAT(format as u16),
+        }
+    }
 
-        if formats.len() <= 0 {
-            panic!("HTML Format not found");
+    pub fn new_with_rtf() -> Self {
+        let format = unsafe { RegisterClipboardFormatW(w!("Rich Text Format")) };
+        if format == 0 {
+            panic!("Rich Text Format not found");
         }
 
         WinClipboard {
             opened: false,
-            clipboard_format: *formats[0],
+            clipboard_format: CLIPBOARD_FORMAT(format as u16),
         }
     }
 
@@ -105,13 +105,25 @@ impl WinClipboard {
         }
     }
 
+    pub fn format(&self) -> CLIPBOARD_FORMAT {
+        self.clipboard_format
+    }
+
     pub fn set_clipboard_data(&self, h_global: HGLOBAL) -> Result<(), Error> {
+        self.set_clipboard_data_as(self.clipboard_format, h_global)
+    }
+
+    /// Sets data of another format while this clipboard is open.
+    pub fn set_clipboard_data_as(
+        &self,
+        format: CLIPBOARD_FORMAT,
+        h_global: HGLOBAL,
+    ) -> Result<(), Error> {
         if !self.opened {
             return Err(Error::new("Clipboard not opened"));
         }
 
-        match unsafe { SetClipboardData(self.clipboard_format.0.into(), Some(HANDLE(h_global.0))) }
-        {
+        match unsafe { SetClipboardData(format.0.into(), Some(HANDLE(h_global.0))) } {
             Ok(_) => Ok(()),
             Err(_) => return Err(Error::new("Failed to set clipboard data")),
         }
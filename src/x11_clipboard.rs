@@ -0,0 +1,588 @@
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    thread::sleep,
+    time::{Duration, Instant},
+};
+
+use x11rb::{
+    connection::{Connection, RequestConnection},
+    protocol::{
+        Event,
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ConnectionExt, CreateWindowAux, EventMask,
+            PropMode, Property, SELECTION_NOTIFY_EVENT, SelectionNotifyEvent,
+            SelectionRequestEvent, Window, WindowClass,
+        },
+    },
+    rust_connection::RustConnection,
+    wrapper::ConnectionExt as _,
+};
+
+use crate::error::Error;
+
+x11rb::atom_manager! {
+    Atoms: AtomsCookie {
+        CLIPBOARD,
+        TARGETS,
+        INCR,
+        ATOM,
+        QLP_SELECTION,
+    }
+}
+
+// how long to wait for the selection owner to answer
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How the owner answers a conversion request.
+#[derive(Debug, PartialEq)]
+enum Reply {
+    Targets(Vec<Atom>),
+    /// index of the offer, small enough for one request
+    Data(usize),
+    /// index of the offer, sent in chunks
+    Incr(usize),
+    Refuse,
+}
+
+/// Decides the reply to a request for `target`, data over `limit` bytes goes by INCR.
+fn negotiate(target: Atom, targets: Atom, offers: &[(Atom, Vec<u8>)], limit: usize) -> Reply {
+    if target == targets {
+        let mut atoms = vec![targets];
+        atoms.extend(offers.iter().map(|(atom, _)| *atom));
+        return Reply::Targets(atoms);
+    }
+
+    match offers.iter().position(|(atom, _)| *atom == target) {
+        Some(i) if offers[i].1.len() < limit => Reply::Data(i),
+        Some(i) => Reply::Incr(i),
+        None => Reply::Refuse,
+    }
+}
+
+/// An INCR transfer in progress, the next chunk is sent when the requestor deletes the property.
+struct Transfer {
+    requestor: Window,
+    property: Atom,
+    offer: usize,
+    sent: usize,
+}
+
+pub struct X11Clipboard {
+    conn: RustConnection,
+    window: Window,
+    atoms: Atoms,
+}
+
+impl X11Clipboard {
+    pub fn new() -> Result<Self, Error> {
+        let (conn, screen_num) = x11rb::connect(None)
+            .map_err(|e| Error::new(format!("Failed to connect to X server: {}", e)))?;
+
+        let root = conn.setup().roots[screen_num].root;
+        let window = conn
+            .generate_id()
+            .map_err(|e| Error::new(format!("Failed to generate window id: {}", e)))?;
+
+        conn.create_window(
+            x11rb::COPY_DEPTH_FROM_PARENT,
+            window,
+            root,
+            0,
+            0,
+            1,
+            1,
+            0,
+            WindowClass::INPUT_ONLY,
+            x11rb::COPY_FROM_PARENT,
+            &CreateWindowAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+        )
+        .map_err(|e| Error::new(format!("Failed to create window: {}", e)))?;
+
+        let atoms = Atoms::new(&conn)
+            .map_err(|e| Error::new(format!("Failed to intern atoms: {}", e)))?
+            .reply()
+            .map_err(|e| Error::new(format!("Failed to intern atoms: {}", e)))?;
+
+        conn.flush()
+            .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+        Ok(Self {
+            conn,
+            window,
+            atoms,
+        })
+    }
+
+    fn intern(&self, name: &str) -> Result<Atom, Error> {
+        self.conn
+            .intern_atom(false, name.as_bytes())
+            .map_err(|e| Error::new(format!("Failed to intern atom: {}", e)))?
+            .reply()
+            .map(|r| r.atom)
+            .map_err(|e| Error::new(format!("Failed to intern atom: {}", e)))
+    }
+
+    fn atom_name(&self, atom: Atom) -> Option<String> {
+        let reply = self.conn.get_atom_name(atom).ok()?.reply().ok()?;
+        String::from_utf8(reply.name).ok()
+    }
+
+    fn wait_for<T>(&self, mut f: impl FnMut(Event) -> Option<T>) -> Result<T, Error> {
+        let deadline = Instant::now() + TIMEOUT;
+
+        loop {
+            let event = self
+                .conn
+                .poll_for_event()
+                .map_err(|e| Error::new(format!("Failed to poll X11 event: {}", e)))?;
+
+            match event {
+                Some(event) => {
+                    if let Some(result) = f(event) {
+                        return Ok(result);
+                    }
+                }
+                None => {
+                    if Instant::now() >= deadline {
+                        return Err(Error::new("Timed out waiting for the selection owner"));
+                    }
+                    sleep(Duration::from_millis(5));
+                }
+            }
+        }
+    }
+
+    fn take_property(&self) -> Result<(Atom, Vec<u8>), Error> {
+        let reply = self
+            .conn
+            .get_property(
+                true,
+                self.window,
+                self.atoms.QLP_SELECTION,
+                AtomEnum::ANY,
+                0,
+                u32::MAX / 4,
+            )
+            .map_err(|e| Error::new(format!("Failed to get property: {}", e)))?
+            .reply()
+            .map_err(|e| Error::new(format!("Failed to get property: {}", e)))?;
+
+        Ok((reply.type_, reply.value))
+    }
+
+    /// Returns the targets currently offered by the CLIPBOARD selection owner.
+    pub fn targets(&self) -> Result<Vec<String>, Error> {
+        let data = match self.convert(self.atoms.TARGETS)? {
+            Some(data) => data,
+            None => return Ok(Vec::new()),
+        };
+
+        Ok(data
+            .chunks_exact(4)
+            .map(|c| u32::from_ne_bytes([c[0], c[1], c[2], c[3]]))
+            .filter_map(|atom| self.atom_name(atom))
+            .collect())
+    }
+
+    /// Reads the CLIPBOARD selection converted to `target`.
+    ///
+    /// Returns `None` when the owner refuses the conversion or there is no owner.
+    pub fn read(&self, target: &str) -> Result<Option<Vec<u8>>, Error> {
+        let target = self.intern(target)?;
+        self.convert(target)
+    }
+
+    fn convert(&self, target: Atom) -> Result<Option<Vec<u8>>, Error> {
+        let owner = self
+            .conn
+            .get_selection_owner(self.atoms.CLIPBOARD)
+            .map_err(|e| Error::new(format!("Failed to get selection owner: {}", e)))?
+            .reply()
+            .map_err(|e| Error::new(format!("Failed to get selection owner: {}", e)))?
+            .owner;
+        if owner == x11rb::NONE {
+            return Ok(None);
+        }
+
+        self.conn
+            .convert_selection(
+                self.window,
+                self.atoms.CLIPBOARD,
+                target,
+                self.atoms.QLP_SELECTION,
+                x11rb::CURRENT_TIME,
+            )
+            .map_err(|e| Error::new(format!("Failed to convert selection: {}", e)))?;
+        self.conn
+            .flush()
+            .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+        let property = self.wait_for(|event| match event {
+            Event::SelectionNotify(e) if e.requestor == self.window => Some(e.property),
+            _ => None,
+        })?;
+
+        if property == x11rb::NONE {
+            return Ok(None);
+        }
+
+        let (type_, value) = self.take_property()?;
+        if type_ != self.atoms.INCR {
+            return Ok(Some(value));
+        }
+
+        // large data is transferred in chunks, an empty chunk terminates it
+        self.conn
+            .flush()
+            .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+        let mut data = Vec::new();
+        loop {
+            self.wait_for(|event| match event {
+                Event::PropertyNotify(e)
+                    if e.window == self.window
+                        && e.atom == self.atoms.QLP_SELECTION
+                        && e.state == Property::NEW_VALUE =>
+                {
+                    Some(())
+                }
+                _ => None,
+            })?;
+
+            let (_, chunk) = self.take_property()?;
+            self.conn
+                .flush()
+                .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+            if chunk.is_empty() {
+                break;
+            }
+            data.extend(chunk);
+        }
+
+        Ok(Some(data))
+    }
+
+    /// Takes ownership of the CLIPBOARD selection and offers `contents` as (target, data) pairs.
+    ///
+    /// X11 selections are served by their owner, so a child process is forked to answer
+    /// requests until another client takes the selection over.
+    pub fn write(&self, contents: Vec<(String, Vec<u8>)>) -> Result<(), Error> {
+        let (mut parent, mut child) = UnixStream::pair()
+            .map_err(|e| Error::new(format!("Failed to create socket pair: {}", e)))?;
+
+        match unsafe { libc::fork() } {
+            -1 => Err(Error::new("Failed to fork selection owner")),
+            0 => {
+                drop(parent);
+                // the owner outlives the command, errors go through the socket
+                detach_stdio();
+                let code = match X11Clipboard::new().and_then(|c| c.own(contents, &mut child)) {
+                    Ok(_) => 0,
+                    Err(e) => {
+                        let _ = child.write_all(e.to_string().as_bytes());
+                        1
+                    }
+                };
+                unsafe { libc::_exit(code) }
+            }
+            _ => {
+                drop(child);
+                // the owner reports "ok" once the selection is taken, or an error message
+                let mut message = String::new();
+                let _ = parent.read_to_string(&mut message);
+
+                match message.as_str() {
+                    "ok" => Ok(()),
+                    "" => Err(Error::new("Selection owner exited unexpectedly")),
+                    _ => Err(Error::new(message)),
+                }
+            }
+        }
+    }
+
+    fn own(&self, contents: Vec<(String, Vec<u8>)>, ready: &mut UnixStream) -> Result<(), Error> {
+        let mut offers = Vec::new();
+        for (target, data) in contents {
+            offers.push((self.intern(&target)?, data));
+        }
+
+        self.conn
+            .set_selection_owner(self.window, self.atoms.CLIPBOARD, x11rb::CURRENT_TIME)
+            .map_err(|e| Error::new(format!("Failed to set selection owner: {}", e)))?;
+
+        let owner = self
+            .conn
+            .get_selection_owner(self.atoms.CLIPBOARD)
+            .map_err(|e| Error::new(format!("Failed to get selection owner: {}", e)))?
+            .reply()
+            .map_err(|e| Error::new(format!("Failed to get selection owner: {}", e)))?
+            .owner;
+        if owner != self.window {
+            return Err(Error::new("Failed to take ownership of the clipboard"));
+        }
+
+        let _ = ready.write_all(b"ok");
+        let _ = ready.shutdown(std::net::Shutdown::Both);
+
+        // transfers already started are finished after losing the selection
+        let mut transfers: Vec<Transfer> = Vec::new();
+        let mut cleared = false;
+        while !(cleared && transfers.is_empty()) {
+            let event = self
+                .conn
+                .wait_for_event()
+                .map_err(|e| Error::new(format!("Failed to wait X11 event: {}", e)))?;
+
+            match event {
+                Event::SelectionRequest(request) if !cleared => {
+                    if let Some(transfer) = self.answer(&request, &offers)? {
+                        transfers.push(transfer);
+                    }
+                }
+                // the selection belongs to another client now
+                Event::SelectionRequest(request) => self.notify(&request, x11rb::NONE)?,
+                Event::PropertyNotify(e) if e.state == Property::DELETE => {
+                    if let Some(i) = transfers
+                        .iter()
+                        .position(|t| t.requestor == e.window && t.property == e.atom)
+                        && !self.send_chunk(&mut transfers[i], &offers)?
+                    {
+                        transfers.remove(i);
+                    }
+                }
+                Event::SelectionClear(_) => cleared = true,
+                _ => {}
+            }
+        }
+
+        // requests already queued would otherwise wait until they time out
+        while let Some(event) = self
+            .conn
+            .poll_for_event()
+            .map_err(|e| Error::new(format!("Failed to poll X11 event: {}", e)))?
+        {
+            if let Event::SelectionRequest(request) = event {
+                self.notify(&request, x11rb::NONE)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn chunk_size(&self) -> usize {
+        self.conn.maximum_request_bytes() / 4
+    }
+
+    /// Sends the next chunk of `transfer`, returns false once the closing empty chunk is sent.
+    fn send_chunk(
+        &self,
+        transfer: &mut Transfer,
+        offers: &[(Atom, Vec<u8>)],
+    ) -> Result<bool, Error> {
+        let (target, data) = &offers[transfer.offer];
+        let end = data.len().min(transfer.sent + self.chunk_size());
+
+        self.conn
+            .change_property8(
+                PropMode::REPLACE,
+                transfer.requestor,
+                transfer.property,
+                *target,
+                &data[transfer.sent..end],
+            )
+            .map_err(|e| Error::new(format!("Failed to change property: {}", e)))?;
+        self.conn
+            .flush()
+            .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+        let more = transfer.sent < data.len();
+        transfer.sent = end;
+        Ok(more)
+    }
+
+    fn answer(
+        &self,
+        request: &SelectionRequestEvent,
+        offers: &[(Atom, Vec<u8>)],
+    ) -> Result<Option<Transfer>, Error> {
+        // obsolete clients leave the property empty
+        let property = if request.property == x11rb::NONE {
+            request.target
+        } else {
+            request.property
+        };
+
+        // room for the request header
+        let limit = self.conn.maximum_request_bytes() - 64;
+
+        let mut transfer = None;
+        let stored = match negotiate(request.target, self.atoms.TARGETS, offers, limit) {
+            Reply::Targets(targets) => self
+                .conn
+                .change_property32(
+                    PropMode::REPLACE,
+                    request.requestor,
+                    property,
+                    self.atoms.ATOM,
+                    &targets,
+                )
+                .is_ok(),
+            Reply::Data(i) => self
+                .conn
+                .change_property8(
+                    PropMode::REPLACE,
+                    request.requestor,
+                    property,
+                    offers[i].0,
+                    &offers[i].1,
+                )
+                .is_ok(),
+            Reply::Incr(i) => {
+                // the requestor deleting the property asks for the next chunk
+                let watched = self
+                    .conn
+                    .change_window_attributes(
+                        request.requestor,
+                        &ChangeWindowAttributesAux::new().event_mask(EventMask::PROPERTY_CHANGE),
+                    )
+                    .is_ok();
+                let announced = watched
+                    && self
+                        .conn
+                        .change_property32(
+                            PropMode::REPLACE,
+                            request.requestor,
+                            property,
+                            self.atoms.INCR,
+                            &[offers[i].1.len() as u32],
+                        )
+                        .is_ok();
+                if announced {
+                    transfer = Some(Transfer {
+                        requestor: request.requestor,
+                        property,
+                        offer: i,
+                        sent: 0,
+                    });
+                }
+                announced
+            }
+            Reply::Refuse => false,
+        };
+
+        self.notify(request, if stored { property } else { x11rb::NONE })?;
+
+        Ok(transfer)
+    }
+
+    /// Tells the requestor where the data is, `NONE` refuses the request.
+    fn notify(&self, request: &SelectionRequestEvent, property: Atom) -> Result<(), Error> {
+        let event = SelectionNotifyEvent {
+            response_type: SELECTION_NOTIFY_EVENT,
+            sequence: 0,
+            time: request.time,
+            requestor: request.requestor,
+            selection: request.selection,
+            target: request.target,
+            property,
+        };
+
+        self.conn
+            .send_event(false, request.requestor, EventMask::NO_EVENT, event)
+            .map_err(|e| Error::new(format!("Failed to send selection notify: {}", e)))?;
+        self.conn
+            .flush()
+            .map_err(|e| Error::new(format!("Failed to flush connection: {}", e)))?;
+
+        Ok(())
+    }
+}
+
+/// Points stdin, stdout and stderr at /dev/null so the owner does not hold the terminal or pipes.
+fn detach_stdio() {
+    unsafe {
+        let null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if null < 0 {
+            return;
+        }
+        for fd in 0..=2 {
+            libc::dup2(null, fd);
+        }
+        if null > 2 {
+            libc::close(null);
+        }
+    }
+}
+
+impl Drop for X11Clipboard {
+    fn drop(&mut self) {
+        let _ = self.conn.destroy_window(self.window);
+        let _ = self.conn.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TARGETS: Atom = 1;
+    const HTML: Atom = 2;
+    const TEXT: Atom = 3;
+
+    #[test]
+    fn test_negotiate() {
+        let offers = vec![(HTML, vec![0; 100]), (TEXT, b"qlp".to_vec())];
+
+        assert_eq!(
+            Reply::Targets(vec![TARGETS, HTML, TEXT]),
+            negotiate(TARGETS, TARGETS, &offers, 1000)
+        );
+        assert_eq!(Reply::Data(1), negotiate(TEXT, TARGETS, &offers, 1000));
+        assert_eq!(Reply::Data(0), negotiate(HTML, TARGETS, &offers, 1000));
+        // larger than one request
+        assert_eq!(Reply::Incr(0), negotiate(HTML, TARGETS, &offers, 100));
+        assert_eq!(Reply::Refuse, negotiate(4, TARGETS, &offers, 1000));
+    }
+
+    #[test]
+    #[ignore = "requires an X server (e.g. Xvfb)"]
+    fn test_write_and_read() {
+        let clipboard = X11Clipboard::new().unwrap();
+
+        clipboard
+            .write(vec![
+                ("text/html".to_string(), b"<b>qlp</b>".to_vec()),
+                ("UTF8_STRING".to_string(), b"qlp".to_vec()),
+            ])
+            .unwrap();
+
+        let targets = clipboard.targets().unwrap();
+        assert!(targets.contains(&"text/html".to_string()));
+        assert!(targets.contains(&"UTF8_STRING".to_string()));
+
+        assert_eq!(
+            Some(b"<b>qlp</b>".to_vec()),
+            clipboard.read("text/html").unwrap()
+        );
+        assert_eq!(
+            Some(b"qlp".to_vec()),
+            clipboard.read("UTF8_STRING").unwrap()
+        );
+        assert_eq!(None, clipboard.read("image/png").unwrap());
+    }
+
+    #[test]
+    #[ignore = "requires an X server (e.g. Xvfb)"]
+    fn test_write_and_read_incr() {
+        let clipboard = X11Clipboard::new().unwrap();
+        let large = "qlp ".repeat(1 << 20).into_bytes();
+
+        clipboard
+            .write(vec![("UTF8_STRING".to_string(), large.clone())])
+            .unwrap();
+
+        assert_eq!(Some(large), clipboard.read("UTF8_STRING").unwrap());
+    }
+}
@@ -0,0 +1,769 @@
+//! XML documents parsed with xml5ever, with formatting and XPath-style queries
+//!
+//! Queries support a subset of XPath: `/a/b`, `//b`, `*`, `@attr`, `text()` and the
+//! predicates `[n]`, `[last()]`, `[@attr]`, `[@attr='v']`, `[child]` and `[child='v']`.
+//! Names without a prefix match the local name, so `//dependency` finds the
+//! elements of a POM in its default namespace.
+
+use std::collections::HashSet;
+
+use markup5ever_rcdom::{Handle, NodeData, RcDom};
+use xml5ever::{
+    driver::parse_document,
+    tendril::{StrTendril, TendrilSink},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct XmlElement {
+    pub prefix: Option<String>,
+    pub local_name: String,
+    pub namespace: Option<String>,
+    /// qualified names such as `xmlns:soap` in document order
+    pub attrs: Vec<(String, String)>,
+    pub children: Vec<XmlNode>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum XmlNode {
+    Element(XmlElement),
+    Text(String),
+    Comment(String),
+    ProcessingInstruction { target: String, data: String },
+    Doctype(String),
+}
+
+impl XmlElement {
+    pub fn new(name: &str) -> Self {
+        let (prefix, local_name) = match name.split_once(':') {
+            Some((prefix, local_name)) => (Some(prefix.to_string()), local_name.to_string()),
+            None => (None, name.to_string()),
+        };
+
+        Self {
+            prefix,
+            local_name,
+            namespace: None,
+            attrs: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    /// The name as written, `soap:Envelope`.
+    pub fn name(&self) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("{}:{}", prefix, self.local_name),
+            None => self.local_name.clone(),
+        }
+    }
+
+    pub fn get_attr(&self, name: &str) -> Option<&str> {
+        self.attrs
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn elements(&self) -> impl Iterator<Item = &XmlElement> {
+        self.children.iter().filter_map(|child| match child {
+            XmlNode::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// The first element at the top of a document.
+    pub fn root(&self) -> Option<&XmlElement> {
+        self.elements().next()
+    }
+
+    /// The text of all descendants.
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for child in &self.children {
+            match child {
+                XmlNode::Text(t) => text.push_str(t),
+                XmlNode::Element(e) => text.push_str(&e.text()),
+                _ => {}
+            }
+        }
+
+        text
+    }
+
+    fn matches(&self, name: &str) -> bool {
+        name == "*"
+            || if name.contains(':') {
+                self.name() == name
+            } else {
+                self.local_name == name
+            }
+    }
+}
+
+/// Parses a document, the result is an element without a name holding the top level nodes.
+///
+/// Documents that are not well-formed are an error rather than what xml5ever recovers of them.
+pub fn parse_xml(xml: &str) -> Result<XmlElement, String> {
+    let dom = parse_document(RcDom::default(), Default::default()).one(StrTendril::from(xml));
+
+    let errors = dom.errors.borrow();
+    if !errors.is_empty() {
+        return Err(format!("XML parse error: {}", errors.join(", ")));
+    }
+
+    let mut document = XmlElement::new("");
+    document.children = convert_children(&dom.document, &mut Vec::new());
+    Ok(document)
+}
+
+/// Adds the declaration of `prefix` unless it is already bound to `ns`.
+fn declare(
+    scope: &mut Vec<(Option<String>, String)>,
+    prefix: Option<String>,
+    ns: String,
+    attrs: &mut Vec<(String, String)>,
+) {
+    if prefix.as_deref() == Some("xml") {
+        return;
+    }
+
+    let bound = scope
+        .iter()
+        .rev()
+        .find(|(p, _)| *p == prefix)
+        .map(|(_, ns)| ns.as_str())
+        .unwrap_or_default();
+    if bound != ns {
+        let name = match &prefix {
+            Some(prefix) => format!("xmlns:{}", prefix),
+            None => "xmlns".to_string(),
+        };
+        attrs.push((name, ns.clone()));
+        scope.push((prefix, ns));
+    }
+}
+
+/// `scope` holds the namespace bindings of the ancestors, xml5ever drops the `xmlns`
+/// attributes so they are declared again where a binding changes.
+fn convert_children(handle: &Handle, scope: &mut Vec<(Option<String>, String)>) -> Vec<XmlNode> {
+    let mut nodes: Vec<XmlNode> = Vec::new();
+
+    for child in handle.children.borrow().iter() {
+        let node = match &child.data {
+            NodeData::Element { name, attrs, .. } => {
+                let scope_len = scope.len();
+                let mut declarations = Vec::new();
+                declare(
+                    scope,
+                    name.prefix.as_ref().map(|p| p.to_string()),
+                    name.ns.to_string(),
+                    &mut declarations,
+                );
+
+                let mut element_attrs = Vec::new();
+                for attr in attrs.borrow().iter() {
+                    let attr_name = match &attr.name.prefix {
+                        Some(prefix) => {
+                            declare(
+                                scope,
+                                Some(prefix.to_string()),
+                                attr.name.ns.to_string(),
+                                &mut declarations,
+                            );
+                            format!("{}:{}", prefix, attr.name.local)
+                        }
+                        None => attr.name.local.to_string(),
+                    };
+                    element_attrs.push((attr_name, attr.value.to_string()));
+                }
+                declarations.append(&mut element_attrs);
+
+                let element = XmlElement {
+                    prefix: name.prefix.as_ref().map(|p| p.to_string()),
+                    local_name: name.local.to_string(),
+                    namespace: Some(name.ns.to_string()).filter(|ns| !ns.is_empty()),
+                    attrs: declarations,
+                    children: convert_children(child, scope),
+                };
+                scope.truncate(scope_len);
+
+                XmlNode::Element(element)
+            }
+            NodeData::Text { contents } => {
+                let text = contents.borrow().to_string();
+                // CDATA sections may come as separate text nodes
+                if let Some(XmlNode::Text(last)) = nodes.last_mut() {
+                    last.push_str(&text);
+                    continue;
+                }
+                XmlNode::Text(text)
+            }
+            NodeData::Comment { contents } => XmlNode::Comment(contents.to_string()),
+            NodeData::ProcessingInstruction { target, contents } => {
+                XmlNode::ProcessingInstruction {
+                    target: target.to_string(),
+                    data: contents.to_string(),
+                }
+            }
+            NodeData::Doctype {
+                name,
+                public_id,
+                system_id,
+            } => XmlNode::Doctype(match (public_id.is_empty(), system_id.is_empty()) {
+                (false, _) => format!("{} PUBLIC \"{}\" \"{}\"", name, public_id, system_id),
+                (true, false) => format!("{} SYSTEM \"{}\"", name, system_id),
+                (true, true) => name.to_string(),
+            }),
+            NodeData::Document => continue,
+        };
+
+        nodes.push(node);
+    }
+
+    nodes
+}
+
+fn escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+fn escape_attr(value: &str) -> String {
+    escape_text(value).replace('"', "&quot;")
+}
+
+fn is_blank(node: &XmlNode) -> bool {
+    matches!(node, XmlNode::Text(t) if t.trim().is_empty())
+}
+
+/// Serializes nodes, `indent` puts each element on its own line.
+///
+/// Text mixed with elements is written as it is, whitespace-only text between
+/// elements is replaced by the indentation or dropped.
+pub fn write_xml(nodes: &[XmlNode], indent: Option<&str>, comments: bool) -> String {
+    let mut out = String::new();
+    write_nodes(nodes, indent, comments, 0, &mut out);
+
+    if indent.is_some() {
+        out.push('\n');
+    }
+    out
+}
+
+fn write_nodes(
+    nodes: &[XmlNode],
+    indent: Option<&str>,
+    comments: bool,
+    depth: usize,
+    out: &mut String,
+) {
+    let mut first = true;
+    for node in nodes {
+        if is_blank(node) || (!comments && matches!(node, XmlNode::Comment(_))) {
+            continue;
+        }
+
+        if let Some(indent) = indent {
+            if !first || depth > 0 {
+                out.push('\n');
+            }
+            out.push_str(&indent.repeat(depth));
+        }
+        first = false;
+
+        write_node(node, indent, comments, depth, out);
+    }
+}
+
+fn write_node(
+    node: &XmlNode,
+    indent: Option<&str>,
+    comments: bool,
+    depth: usize,
+    out: &mut String,
+) {
+    match node {
+        XmlNode::Element(e) => write_element(e, indent, comments, depth, out),
+        XmlNode::Text(text) => out.push_str(&escape_text(text)),
+        XmlNode::Comment(text) => {
+            out.push_str("<!--");
+            out.push_str(text);
+            out.push_str("-->");
+        }
+        XmlNode::ProcessingInstruction { target, data } => {
+            out.push_str("<?");
+            out.push_str(target);
+            if !data.is_empty() {
+                out.push(' ');
+                out.push_str(data);
+            }
+            out.push_str("?>");
+        }
+        XmlNode::Doctype(doctype) => {
+            out.push_str("<!DOCTYPE ");
+            out.push_str(doctype);
+            out.push('>');
+        }
+    }
+}
+
+fn write_element(
+    e: &XmlElement,
+    indent: Option<&str>,
+    comments: bool,
+    depth: usize,
+    out: &mut String,
+) {
+    let name = e.name();
+    out.push('<');
+    out.push_str(&name);
+    for (k, v) in &e.attrs {
+        out.push_str(&format!(" {}=\"{}\"", k, escape_attr(v)));
+    }
+
+    let has_elements = e.children.iter().any(|c| !matches!(c, XmlNode::Text(_)));
+    let mixed = has_elements
+        && e.children
+            .iter()
+            .any(|c| matches!(c, XmlNode::Text(_)) && !is_blank(c));
+
+    if e.children.is_empty() || (has_elements && e.children.iter().all(is_blank)) {
+        out.push_str("/>");
+        return;
+    }
+    out.push('>');
+
+    if !has_elements {
+        // text only
+        for child in &e.children {
+            write_node(child, None, comments, 0, out);
+        }
+    } else if mixed {
+        // whitespace is part of the content
+        for child in &e.children {
+            if comments || !matches!(child, XmlNode::Comment(_)) {
+                write_node(child, None, comments, 0, out);
+            }
+        }
+    } else {
+        write_nodes(&e.children, indent, comments, depth + 1, out);
+        if let Some(indent) = indent {
+            out.push('\n');
+            out.push_str(&indent.repeat(depth));
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&name);
+    out.push('>');
+}
+
+pub fn prettier_xml(xml: &str, indent: &str) -> Result<String, String> {
+    Ok(write_xml(&parse_xml(xml)?.children, Some(indent), true))
+}
+
+pub fn minify_xml(xml: &str) -> Result<String, String> {
+    Ok(write_xml(&parse_xml(xml)?.children, None, false))
+}
+
+#[derive(Debug)]
+pub enum XmlMatch<'a> {
+    Element(&'a XmlElement),
+    Text(String),
+}
+
+#[derive(Debug, PartialEq)]
+enum NodeTest {
+    Name(String),
+    Attr(String),
+    Text,
+}
+
+#[derive(Debug, PartialEq)]
+enum Predicate {
+    Position(usize),
+    Last,
+    Attr(String, Option<String>),
+    Child(String, Option<String>),
+}
+
+#[derive(Debug, PartialEq)]
+struct Step {
+    descendant: bool,
+    test: NodeTest,
+    predicates: Vec<Predicate>,
+}
+
+fn query_error(path: &str, message: &str) -> String {
+    format!("invalid query {:?}: {}", path, message)
+}
+
+/// Splits at `sep` outside of brackets and quotes.
+fn split_outside(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote: Option<char> = None;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '[') => depth += 1,
+            (None, ']') => depth -= 1,
+            (None, c) if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+
+    parts
+}
+
+fn find_closing_bracket(s: &str) -> Option<usize> {
+    let mut quote: Option<char> = None;
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, ']') => return Some(i),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn unquote(s: &str) -> Option<String> {
+    let s = s.trim();
+    ['\'', '"'].iter().find_map(|q| {
+        s.strip_prefix(*q)
+            .and_then(|s| s.strip_suffix(*q))
+            .map(|s| s.to_string())
+    })
+}
+
+fn parse_predicate(path: &str, predicate: &str) -> Result<Predicate, String> {
+    let predicate = predicate.trim();
+
+    if let Ok(n) = predicate.parse::<usize>() {
+        return match n {
+            0 => Err(query_error(path, "positions start at 1")),
+            n => Ok(Predicate::Position(n)),
+        };
+    }
+    if predicate == "last()" {
+        return Ok(Predicate::Last);
+    }
+
+    let (name, value) = match predicate.split_once('=') {
+        Some((name, value)) => (
+            name.trim(),
+            Some(unquote(value).ok_or_else(|| query_error(path, "values must be quoted"))?),
+        ),
+        None => (predicate, None),
+    };
+
+    match name.strip_prefix('@') {
+        Some(attr) => Ok(Predicate::Attr(attr.to_string(), value)),
+        None if !name.is_empty() => Ok(Predicate::Child(name.to_string(), value)),
+        None => Err(query_error(path, "empty predicate")),
+    }
+}
+
+fn parse_step(path: &str, step: &str, descendant: bool) -> Result<Step, String> {
+    let (test, mut rest) = match step.find('[') {
+        Some(i) => (step[..i].trim(), &step[i..]),
+        None => (step.trim(), ""),
+    };
+
+    let mut predicates = Vec::new();
+    while let Some(inner) = rest.strip_prefix('[') {
+        let end =
+            find_closing_bracket(inner).ok_or_else(|| query_error(path, "unclosed predicate"))?;
+        predicates.push(parse_predicate(path, &inner[..end])?);
+        rest = inner[end + 1..].trim_start();
+    }
+    if !rest.is_empty() {
+        return Err(query_error(path, &format!("unexpected {:?}", rest)));
+    }
+
+    let test = if test == "text()" {
+        NodeTest::Text
+    } else if let Some(attr) = test.strip_prefix('@') {
+        NodeTest::Attr(attr.to_string())
+    } else if !test.is_empty() {
+        NodeTest::Name(test.to_string())
+    } else {
+        return Err(query_error(path, "empty step"));
+    };
+
+    Ok(Step {
+        descendant,
+        test,
+        predicates,
+    })
+}
+
+fn parse_query(path: &str) -> Result<Vec<Step>, String> {
+    let parts = split_outside(path.trim(), '/');
+    let mut steps = Vec::new();
+    let mut descendant = false;
+
+    for (i, part) in parts.iter().enumerate() {
+        if part.trim().is_empty() {
+            // `//`, or the leading `/`
+            if i > 0 && i < parts.len() - 1 {
+                descendant = true;
+            } else if i > 0 {
+                return Err(query_error(path, "trailing slash"));
+            }
+            continue;
+        }
+
+        steps.push(parse_step(path, part, descendant)?);
+        descendant = false;
+    }
+
+    if let Some(i) = steps
+        .iter()
+        .position(|s| !matches!(s.test, NodeTest::Name(_)))
+        && i != steps.len() - 1
+    {
+        return Err(query_error(path, "@attr and text() must be the last step"));
+    }
+
+    Ok(steps)
+}
+
+fn descendants_or_self<'a>(e: &'a XmlElement, out: &mut Vec<&'a XmlElement>) {
+    out.push(e);
+    for child in e.elements() {
+        descendants_or_self(child, out);
+    }
+}
+
+fn filter<'a>(candidates: Vec<&'a XmlElement>, predicates: &[Predicate]) -> Vec<&'a XmlElement> {
+    let mut candidates = candidates;
+
+    for predicate in predicates {
+        candidates = match predicate {
+            Predicate::Position(n) => candidates.get(n - 1).into_iter().copied().collect(),
+            Predicate::Last => candidates.last().into_iter().copied().collect(),
+            Predicate::Attr(name, value) => candidates
+                .into_iter()
+                .filter(|e| match e.get_attr(name) {
+                    Some(v) => value.as_deref().is_none_or(|value| v == value),
+                    None => false,
+                })
+                .collect(),
+            Predicate::Child(name, value) => candidates
+                .into_iter()
+                .filter(|e| {
+                    e.elements().any(|c| {
+                        c.matches(name) && value.as_deref().is_none_or(|value| c.text() == value)
+                    })
+                })
+                .collect(),
+        };
+    }
+
+    candidates
+}
+
+/// Runs a query from `context`, a document from [`parse_xml`] or an element.
+pub fn query_xml<'a>(context: &'a XmlElement, path: &str) -> Result<Vec<XmlMatch<'a>>, String> {
+    let steps = parse_query(path)?;
+    let mut current = vec![context];
+
+    for step in &steps {
+        let parents = if step.descendant {
+            let mut all = Vec::new();
+            for e in &current {
+                descendants_or_self(e, &mut all);
+            }
+            // nested context elements would yield the same children twice
+            if current.len() > 1 {
+                let mut seen = HashSet::new();
+                all.retain(|e| seen.insert(*e as *const XmlElement));
+            }
+            all
+        } else {
+            current
+        };
+
+        match &step.test {
+            NodeTest::Name(name) => {
+                current = parents
+                    .into_iter()
+                    .flat_map(|parent| {
+                        filter(
+                            parent.elements().filter(|c| c.matches(name)).collect(),
+                            &step.predicates,
+                        )
+                    })
+                    .collect();
+            }
+            NodeTest::Attr(name) => {
+                return Ok(parents
+                    .into_iter()
+                    .flat_map(|e| {
+                        e.attrs
+                            .iter()
+                            .filter(|(n, _)| name == "*" || n == name)
+                            .map(|(_, v)| XmlMatch::Text(v.clone()))
+                    })
+                    .collect());
+            }
+            NodeTest::Text => {
+                return Ok(parents
+                    .into_iter()
+                    .flat_map(|e| {
+                        e.children.iter().filter_map(|c| match c {
+                            XmlNode::Text(t) if !t.trim().is_empty() => {
+                                Some(XmlMatch::Text(t.clone()))
+                            }
+                            _ => None,
+                        })
+                    })
+                    .collect());
+            }
+        }
+    }
+
+    Ok(current.into_iter().map(XmlMatch::Element).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POM: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- build -->
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+  <version>1.0</version>
+  <dependencies>
+    <dependency scope="test"><artifactId>junit</artifactId><version>4.13</version></dependency>
+    <dependency><artifactId>guava</artifactId><version><![CDATA[33.0]]></version></dependency>
+  </dependencies>
+</project>"#;
+
+    fn texts(matches: Vec<XmlMatch>) -> Vec<String> {
+        matches
+            .into_iter()
+            .map(|m| match m {
+                XmlMatch::Element(e) => e.text(),
+                XmlMatch::Text(t) => t,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_xml() {
+        let doc = parse_xml(
+            r#"<soap:Envelope xmlns:soap="http://www.w3.org/2003/05/soap-envelope"><soap:Body a="1">x &amp; y</soap:Body></soap:Envelope>"#,
+        )
+        .unwrap();
+        let root = doc.root().unwrap();
+        assert_eq!("soap:Envelope", root.name());
+        assert_eq!("Envelope", root.local_name);
+        assert_eq!(
+            Some("http://www.w3.org/2003/05/soap-envelope"),
+            root.namespace.as_deref()
+        );
+        assert_eq!(
+            Some("http://www.w3.org/2003/05/soap-envelope"),
+            root.get_attr("xmlns:soap")
+        );
+
+        let body = root.root().unwrap();
+        assert_eq!(Some("1"), body.get_attr("a"));
+        assert_eq!("x & y", body.text());
+
+        assert!(parse_xml("<a><b></a>").is_err());
+        assert!(parse_xml("not xml").is_err());
+    }
+
+    #[test]
+    fn test_prettier_xml() {
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- build -->
+<project xmlns="http://maven.apache.org/POM/4.0.0">
+    <version>1.0</version>
+    <dependencies>
+        <dependency scope="test">
+            <artifactId>junit</artifactId>
+            <version>4.13</version>
+        </dependency>
+        <dependency>
+            <artifactId>guava</artifactId>
+            <version>33.0</version>
+        </dependency>
+    </dependencies>
+</project>
+"#,
+            prettier_xml(POM, "    ").unwrap()
+        );
+
+        assert_eq!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><project xmlns="http://maven.apache.org/POM/4.0.0"><version>1.0</version><dependencies><dependency scope="test"><artifactId>junit</artifactId><version>4.13</version></dependency><dependency><artifactId>guava</artifactId><version>33.0</version></dependency></dependencies></project>"#,
+            minify_xml(POM).unwrap()
+        );
+
+        // mixed content keeps its spaces
+        assert_eq!(
+            "<p>\n  <b>a <i>b</i> c</b>\n  <br/>\n</p>\n",
+            prettier_xml("<p> <b>a <i>b</i> c</b><br></br></p>", "  ").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_query_xml() {
+        let doc = parse_xml(POM).unwrap();
+
+        assert_eq!(
+            vec!["junit", "guava"],
+            texts(query_xml(&doc, "//dependency/artifactId").unwrap())
+        );
+        assert_eq!(
+            vec!["1.0"],
+            texts(query_xml(&doc, "/project/version").unwrap())
+        );
+        assert_eq!(
+            vec!["4.13"],
+            texts(query_xml(&doc, "//dependency[@scope='test']/version/text()").unwrap())
+        );
+        assert_eq!(
+            vec!["33.0"],
+            texts(query_xml(&doc, "//dependency[artifactId=\"guava\"]/version").unwrap())
+        );
+        assert_eq!(
+            vec!["guava"],
+            texts(query_xml(&doc, "project/dependencies/*[last()]/artifactId").unwrap())
+        );
+        assert_eq!(
+            vec!["junit"],
+            texts(query_xml(&doc, "//dependency[1]/artifactId").unwrap())
+        );
+        assert_eq!(vec!["test"], texts(query_xml(&doc, "//@scope").unwrap()));
+
+        // relative to an element
+        let dependencies = doc.root().unwrap().elements().nth(1).unwrap();
+        assert_eq!(2, query_xml(dependencies, "dependency").unwrap().len());
+        // nested elements are searched once
+        assert_eq!(
+            vec!["1.0", "4.13", "33.0"],
+            texts(query_xml(&doc, "//*//version").unwrap())
+        );
+
+        assert!(query_xml(&doc, "//@scope/x").is_err());
+        assert!(query_xml(&doc, "//dependency[0]").is_err());
+        assert!(query_xml(&doc, "//dependency[@scope").is_err());
+    }
+}